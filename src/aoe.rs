@@ -0,0 +1,314 @@
+use crate::components::*;
+use crate::los::*;
+use crate::map::get_tile_idx;
+use crate::resources::Map;
+use bevy::ecs::query::QueryFilter;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum AreaOfEffect {
+    /// Everything around the target.
+    Circle { radius: i32 },
+    /// Fans out from the caster towards the target.
+    Cone { radius: i32, spread_degrees: f32 },
+    /// A straight line from the caster through the target, stopped by walls.
+    Beam { length: i32 },
+    /// Flies towards the target and bursts on the first thing it hits.
+    Ball { radius: i32 },
+}
+
+/// Floor tiles covered by `area` when aimed from `origin` at `target`.
+pub fn tiles_in_area<F: QueryFilter>(
+    map: &Map,
+    query_tiles: &Query<&Tile, F>,
+    origin: (usize, usize),
+    target: (usize, usize),
+    area: AreaOfEffect,
+) -> Vec<(usize, usize)> {
+    match area {
+        AreaOfEffect::Circle { radius } => circle(map, query_tiles, target, radius),
+        AreaOfEffect::Cone {
+            radius,
+            spread_degrees,
+        } => cone(map, query_tiles, origin, target, radius, spread_degrees),
+        AreaOfEffect::Beam { length } => beam(map, query_tiles, origin, target, length),
+        AreaOfEffect::Ball { radius } => ball(map, query_tiles, origin, target, radius),
+    }
+}
+
+pub fn circle<F: QueryFilter>(
+    map: &Map,
+    query_tiles: &Query<&Tile, F>,
+    center: (usize, usize),
+    radius: i32,
+) -> Vec<(usize, usize)> {
+    tiles_within(map, center, radius)
+        .into_iter()
+        .filter(|&tile| is_floor(map, query_tiles, tile))
+        .filter(|&tile| line_of_sight(map, query_tiles, center, tile).is_clear())
+        .collect()
+}
+
+pub fn cone<F: QueryFilter>(
+    map: &Map,
+    query_tiles: &Query<&Tile, F>,
+    origin: (usize, usize),
+    target: (usize, usize),
+    radius: i32,
+    spread_degrees: f32,
+) -> Vec<(usize, usize)> {
+    if origin == target {
+        return Vec::new();
+    }
+    let facing = angle_between(origin, target);
+    let half_spread = spread_degrees.to_radians() / 2.0;
+
+    tiles_within(map, origin, radius)
+        .into_iter()
+        .filter(|&tile| tile != origin)
+        .filter(|&tile| {
+            let diff = (angle_between(origin, tile) - facing + std::f32::consts::PI)
+                .rem_euclid(std::f32::consts::TAU)
+                - std::f32::consts::PI;
+            diff.abs() <= half_spread
+        })
+        .filter(|&tile| is_floor(map, query_tiles, tile))
+        .filter(|&tile| line_of_sight(map, query_tiles, origin, tile).is_clear())
+        .collect()
+}
+
+pub fn beam<F: QueryFilter>(
+    map: &Map,
+    query_tiles: &Query<&Tile, F>,
+    origin: (usize, usize),
+    target: (usize, usize),
+    length: i32,
+) -> Vec<(usize, usize)> {
+    if origin == target {
+        return Vec::new();
+    }
+    // Aim past the target so the beam keeps going until its length runs out.
+    let (dx, dy) = (
+        target.0 as f32 - origin.0 as f32,
+        target.1 as f32 - origin.1 as f32,
+    );
+    let scale = length as f32 / dx.abs().max(dy.abs());
+    let end = clamp_to_map(
+        map,
+        (
+            (origin.0 as f32 + dx * scale).round() as i32,
+            (origin.1 as f32 + dy * scale).round() as i32,
+        ),
+    );
+
+    let mut tiles = Vec::new();
+    for tile in bresenham_line(origin, end).into_iter().skip(1) {
+        if !is_floor(map, query_tiles, tile) {
+            break;
+        }
+        tiles.push(tile);
+    }
+    tiles
+}
+
+pub fn ball<F: QueryFilter>(
+    map: &Map,
+    query_tiles: &Query<&Tile, F>,
+    origin: (usize, usize),
+    target: (usize, usize),
+    radius: i32,
+) -> Vec<(usize, usize)> {
+    circle(
+        map,
+        query_tiles,
+        ball_impact(map, query_tiles, origin, target),
+        radius,
+    )
+}
+
+/// Where a ball thrown from `origin` at `target` bursts. It stops on the first creature in
+/// the way, or just in front of a wall.
+pub fn ball_impact<F: QueryFilter>(
+    map: &Map,
+    query_tiles: &Query<&Tile, F>,
+    origin: (usize, usize),
+    target: (usize, usize),
+) -> (usize, usize) {
+    let flight = line_of_fire(map, query_tiles, origin, target);
+    match flight.blocked_at {
+        Some(tile) if !is_floor(map, query_tiles, tile) => {
+            let len = flight.path.len();
+            if len > 1 {
+                flight.path[len - 2]
+            } else {
+                origin
+            }
+        }
+        Some(tile) => tile,
+        None => target,
+    }
+}
+
+pub fn tile_entities(map: &Map, tiles: &[(usize, usize)]) -> Vec<Entity> {
+    tiles
+        .iter()
+        .map(|&(x, y)| map.tiles[get_tile_idx(x, y)])
+        .collect()
+}
+
+fn tiles_within(map: &Map, center: (usize, usize), radius: i32) -> Vec<(usize, usize)> {
+    let (cx, cy) = (center.0 as i32, center.1 as i32);
+    let mut tiles = Vec::new();
+    for y in (cy - radius).max(0)..=(cy + radius).min(map.tile_res.height as i32 - 1) {
+        for x in (cx - radius).max(0)..=(cx + radius).min(map.tile_res.width as i32 - 1) {
+            if (x - cx).pow(2) + (y - cy).pow(2) <= radius * radius {
+                tiles.push((x as usize, y as usize));
+            }
+        }
+    }
+    tiles
+}
+
+fn is_floor<F: QueryFilter>(
+    map: &Map,
+    query_tiles: &Query<&Tile, F>,
+    (x, y): (usize, usize),
+) -> bool {
+    query_tiles
+        .get(map.tiles[get_tile_idx(x, y)])
+        .is_ok_and(|tile| tile.tiletype == TileType::Floor)
+}
+
+fn clamp_to_map(map: &Map, (x, y): (i32, i32)) -> (usize, usize) {
+    (
+        x.clamp(0, map.tile_res.width as i32 - 1) as usize,
+        y.clamp(0, map.tile_res.height as i32 - 1) as usize,
+    )
+}
+
+fn angle_between(from: (usize, usize), to: (usize, usize)) -> f32 {
+    let y = to.1 as f32 - from.1 as f32;
+    let x = to.0 as f32 - from.0 as f32;
+    y.atan2(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::testing::*;
+
+    const ROOM: [&str; 11] = [
+        "###########",
+        "#.........#",
+        "#.........#",
+        "#.........#",
+        "#.........#",
+        "#.........#",
+        "#.........#",
+        "#.........#",
+        "#.........#",
+        "#.........#",
+        "###########",
+    ];
+
+    fn area(
+        rows: &[&str],
+        origin: (usize, usize),
+        target: (usize, usize),
+        area: AreaOfEffect,
+    ) -> Vec<(usize, usize)> {
+        let mut world = test_world(rows);
+        with_tiles(&mut world, |map, query_tiles| {
+            tiles_in_area(map, query_tiles, origin, target, area)
+        })
+    }
+
+    #[test]
+    fn circle_covers_the_tiles_within_its_radius() {
+        let mut tiles = area(&ROOM, (1, 1), (5, 5), AreaOfEffect::Circle { radius: 1 });
+        tiles.sort();
+        assert_eq!(tiles, vec![(4, 5), (5, 4), (5, 5), (5, 6), (6, 5)]);
+        assert_eq!(
+            area(&ROOM, (1, 1), (5, 5), AreaOfEffect::Circle { radius: 2 }).len(),
+            13
+        );
+    }
+
+    #[test]
+    fn circle_stops_at_walls() {
+        let rows = [
+            "###########",
+            "#....#....#",
+            "#....#....#",
+            "#....#....#",
+            "#....#....#",
+            "#....#....#",
+            "###########",
+        ];
+        let tiles = area(&rows, (1, 1), (3, 3), AreaOfEffect::Circle { radius: 4 });
+        assert!(tiles.contains(&(3, 3)));
+        assert!(tiles.iter().all(|&(x, _)| x < 5), "{tiles:?}");
+    }
+
+    #[test]
+    fn cone_fans_out_towards_the_target() {
+        let tiles = area(
+            &ROOM,
+            (2, 5),
+            (6, 5),
+            AreaOfEffect::Cone {
+                radius: 4,
+                spread_degrees: 90.0,
+            },
+        );
+        assert!(tiles.contains(&(6, 5)));
+        assert!(tiles.contains(&(5, 7)));
+        assert!(!tiles.contains(&(2, 5)));
+        assert!(tiles.iter().all(|&(x, y)| x > 2 && y.abs_diff(5) <= x - 2));
+    }
+
+    #[test]
+    fn beam_keeps_going_past_the_target_until_a_wall() {
+        let tiles = area(&ROOM, (2, 5), (4, 5), AreaOfEffect::Beam { length: 20 });
+        assert_eq!(tiles, (3..=9).map(|x| (x, 5)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn beam_is_clamped_at_the_map_edge() {
+        let rows = ["#####", "#####", "....#"];
+        let tiles = area(&rows, (3, 2), (2, 2), AreaOfEffect::Beam { length: 10 });
+        assert_eq!(tiles, vec![(2, 2), (1, 2), (0, 2)]);
+    }
+
+    #[test]
+    fn ball_bursts_on_the_first_creature_in_the_way() {
+        let mut world = test_world(&ROOM);
+        block(&mut world, (4, 5));
+        let tiles = with_tiles(&mut world, |map, query_tiles| {
+            assert_eq!(ball_impact(map, query_tiles, (2, 5), (8, 5)), (4, 5));
+            ball(map, query_tiles, (2, 5), (8, 5), 1)
+        });
+        assert!(tiles.contains(&(4, 5)) && tiles.contains(&(5, 5)));
+        assert!(!tiles.contains(&(8, 5)));
+    }
+
+    #[test]
+    fn ball_bursts_in_front_of_a_wall() {
+        let mut world = test_world(&[
+            "###########",
+            "#.........#",
+            "#.........#",
+            "#.........#",
+            "#.........#",
+            "#..#####..#",
+            "#.........#",
+            "#.........#",
+            "###########",
+        ]);
+        let impact = with_tiles(&mut world, |map, query_tiles| {
+            ball_impact(map, query_tiles, (5, 2), (5, 7))
+        });
+        assert_eq!(impact, (5, 4));
+    }
+}
@@ -0,0 +1,344 @@
+use crate::components::*;
+use crate::events::*;
+use crate::gamelog::GameLog;
+use crate::initiative::ActingSet;
+use crate::random::GameRng;
+use crate::raws::Inflicts;
+use crate::resources::*;
+use crate::turn::*;
+use bevy::prelude::*;
+use rand::Rng;
+
+/// Morale lost for every point of damage taken.
+const MORALE_PER_DAMAGE: i32 = 2;
+
+/// Morale lost by a monster that watches an ally die.
+const ALLY_DEATH_SHOCK: i32 = 4;
+
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Attack>()
+            .add_event::<Damage>()
+            .init_resource::<Death>()
+            .add_systems(
+                Update,
+                (resolve_attacks, apply_damage, remove_dead, kill_player)
+                    .chain()
+                    .after(ActingSet)
+                    .before(TurnSet::EndTurn)
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// Every attack ends up here, whether it was a punch, an arrow or a spell. Attackers that
+/// inflict a status effect get a chance to put it on whatever they hit.
+#[allow(clippy::type_complexity)]
+fn resolve_attacks(
+    mut commands: Commands,
+    mut attacks: EventReader<Attack>,
+    mut damage: EventWriter<Damage>,
+    query_stats: Query<(&CombatStats, &Name, Has<Player>, Option<&Inflicts>)>,
+    turn_count: Res<TurnCount>,
+    mut rng: ResMut<GameRng>,
+    mut log: ResMut<GameLog>,
+) {
+    for attack in attacks.read() {
+        let (Ok(attacker), Ok(target)) = (
+            query_stats.get(attack.attacker),
+            query_stats.get(attack.target),
+        ) else {
+            continue;
+        };
+        let (attacker, attacker_name, player_attacking, inflicts) = attacker;
+        let (target, target_name, _, _) = target;
+        let amount = (attacker.power - target.defense).max(0);
+
+        let verb = match (attack.kind, player_attacking) {
+            (AttackKind::Melee, true) => "hit",
+            (AttackKind::Melee, false) => "hits",
+            (AttackKind::Arrow, true) => "shoot",
+            (AttackKind::Arrow, false) => "shoots",
+            (AttackKind::Spell, true) => "cast at",
+            (AttackKind::Spell, false) => "casts at",
+        };
+        let (message, color) = if player_attacking {
+            let message = format!("You {verb} the {target_name} for {amount}");
+            (message, Color::WHITE)
+        } else {
+            let message = format!("The {attacker_name} {verb} you for {amount}");
+            (message, Color::srgb(1.0, 0.3, 0.3))
+        };
+        log.add(turn_count.0, message, color);
+        damage.send(Damage {
+            source: attack.attacker,
+            target: attack.target,
+            amount,
+        });
+
+        let Some(inflicts) = inflicts else {
+            continue;
+        };
+        if rng.gen_range(0..100) >= inflicts.chance {
+            continue;
+        }
+        inflicts.effect.apply(
+            &mut commands,
+            attack.target,
+            attack.attacker,
+            inflicts.turns,
+        );
+        let message = if player_attacking {
+            format!("The {target_name} is {}", inflicts.effect.describe())
+        } else {
+            format!("You are {}", inflicts.effect.describe())
+        };
+        log.add(turn_count.0, message, Color::srgb(1.0, 0.0, 1.0));
+    }
+}
+
+/// Whoever lands the killing blow on a monster gets its xp.
+#[allow(clippy::type_complexity)]
+fn apply_damage(
+    mut damage: EventReader<Damage>,
+    mut query_stats: Query<(
+        &mut CombatStats,
+        Has<Monster>,
+        Has<Player>,
+        Option<&XpReward>,
+    )>,
+    query_names: Query<&Name>,
+    mut shocks: EventWriter<MoraleShock>,
+    mut xp: EventWriter<GainXp>,
+    mut death: ResMut<Death>,
+) {
+    for damage in damage.read() {
+        let Ok((mut stats, is_monster, is_player, reward)) = query_stats.get_mut(damage.target)
+        else {
+            continue;
+        };
+        let was_alive = stats.hp > 0;
+        stats.hp = (stats.hp - damage.amount).max(0);
+        if is_player && was_alive && stats.hp == 0 {
+            death.cause = query_names
+                .get(damage.source)
+                .map_or("Killed".to_string(), |name| format!("Killed by a {name}"));
+        }
+        if let Some(reward) = reward.filter(|_| was_alive && stats.hp == 0) {
+            xp.send(GainXp {
+                target: damage.source,
+                amount: reward.0,
+            });
+        }
+        if is_monster && damage.amount > 0 {
+            shocks.send(MoraleShock {
+                target: damage.target,
+                amount: damage.amount * MORALE_PER_DAMAGE,
+            });
+        }
+    }
+}
+
+/// Despawns monsters at zero hp and frees up their tile. Monsters that see one die lose heart.
+pub fn remove_dead(
+    mut commands: Commands,
+    query_dead: Query<(Entity, &CombatStats, &Monster, &Name)>,
+    query_witnesses: Query<(Entity, &Viewshed), With<Monster>>,
+    mut query_tiles: Query<&mut Tile>,
+    mut shocks: EventWriter<MoraleShock>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+) {
+    for (dead, stats, monster, name) in query_dead.iter() {
+        if stats.hp > 0 {
+            continue;
+        }
+        log.add(
+            turn_count.0,
+            format!("The {name} dies"),
+            Color::srgb(1.0, 0.6, 0.0),
+        );
+        commands.entity(monster.occupied_tile).remove::<Occupied>();
+        if let Ok(mut tile) = query_tiles.get_mut(monster.occupied_tile) {
+            tile.blocked = false;
+        }
+        commands.entity(dead).despawn_recursive();
+
+        for (witness, viewshed) in query_witnesses.iter() {
+            if witness != dead && viewshed.visible_tiles.contains(&monster.occupied_tile) {
+                shocks.send(MoraleShock {
+                    target: witness,
+                    amount: ALLY_DEATH_SHOCK,
+                });
+            }
+        }
+    }
+}
+
+/// The run is over once the player is out of hp.
+fn kill_player(
+    query_player: Query<&CombatStats, With<Player>>,
+    turn_count: Res<TurnCount>,
+    mut death: ResMut<Death>,
+    mut log: ResMut<GameLog>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Ok(stats) = query_player.get_single() else {
+        return;
+    };
+    if stats.hp == 0 {
+        log.add(turn_count.0, "You die...", Color::srgb(1.0, 0.0, 0.0));
+        death.turns = turn_count.0;
+        next_state.set(GameState::GameOver);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::get_tile_idx;
+    use crate::map::testing::{block, test_world};
+    use bevy::ecs::schedule::Schedule;
+
+    /// The player at (2, 1) next to a Goblin at (3, 1), with a second Goblin watching from
+    /// (6, 1).
+    fn face_off(player_hp: i32) -> (World, Entity, Entity, Entity) {
+        let mut world = test_world(&["#########", "#.......#", "#########"]);
+        world.init_resource::<Events<Attack>>();
+        world.init_resource::<Events<Damage>>();
+        world.init_resource::<Events<MoraleShock>>();
+        world.init_resource::<Events<GainXp>>();
+        world.init_resource::<TurnCount>();
+        world.init_resource::<GameLog>();
+        world.init_resource::<Death>();
+        world.init_resource::<NextState<GameState>>();
+        world.insert_resource(GameRng::from_seed(1));
+        let tile = |world: &World, x| world.resource::<Map>().tiles[get_tile_idx(x, 1)];
+
+        let player = world
+            .spawn((
+                Player,
+                Name::new("Player"),
+                CombatStats {
+                    max_hp: 30,
+                    hp: player_hp,
+                    power: 6,
+                    defense: 1,
+                },
+            ))
+            .id();
+        let goblin_tile = tile(&world, 3);
+        let goblin = world
+            .spawn((
+                Monster {
+                    occupied_tile: goblin_tile,
+                },
+                Name::new("Goblin"),
+                CombatStats {
+                    max_hp: 5,
+                    hp: 5,
+                    power: 4,
+                    defense: 1,
+                },
+                XpReward(10),
+                Viewshed {
+                    visible_tiles: Vec::new(),
+                    range: 8,
+                },
+            ))
+            .id();
+        world.entity_mut(goblin_tile).insert(Occupied);
+        block(&mut world, (3, 1));
+        let witness_tile = tile(&world, 6);
+        let witness = world
+            .spawn((
+                Monster {
+                    occupied_tile: witness_tile,
+                },
+                Name::new("Goblin"),
+                CombatStats {
+                    max_hp: 5,
+                    hp: 5,
+                    power: 4,
+                    defense: 1,
+                },
+                Viewshed {
+                    visible_tiles: vec![goblin_tile],
+                    range: 8,
+                },
+            ))
+            .id();
+        (world, player, goblin, witness)
+    }
+
+    /// Clears out earlier events first, since a fresh schedule reads them all again.
+    fn attack(world: &mut World, attacker: Entity, target: Entity) {
+        world.resource_mut::<Events<Attack>>().clear();
+        world.resource_mut::<Events<Damage>>().clear();
+        world.send_event(Attack {
+            attacker,
+            target,
+            kind: AttackKind::Melee,
+        });
+        let mut schedule = Schedule::default();
+        schedule.add_systems((resolve_attacks, apply_damage, remove_dead, kill_player).chain());
+        schedule.run(world);
+    }
+
+    fn last_message(world: &World) -> &str {
+        &world.resource::<GameLog>().entries.last().unwrap().text
+    }
+
+    #[test]
+    fn defense_soaks_up_damage() {
+        let (mut world, player, goblin, _) = face_off(30);
+        attack(&mut world, goblin, player);
+        assert_eq!(world.get::<CombatStats>(player).unwrap().hp, 27);
+        assert_eq!(last_message(&world), "The Goblin hits you for 3");
+
+        world.get_mut::<CombatStats>(player).unwrap().defense = 10;
+        attack(&mut world, goblin, player);
+        assert_eq!(world.get::<CombatStats>(player).unwrap().hp, 27);
+    }
+
+    #[test]
+    fn a_dead_monster_frees_its_tile_and_pays_out() {
+        let (mut world, player, goblin, witness) = face_off(30);
+        let goblin_tile = world.get::<Monster>(goblin).unwrap().occupied_tile;
+        attack(&mut world, player, goblin);
+
+        assert!(world.get_entity(goblin).is_none());
+        assert!(world.get::<Occupied>(goblin_tile).is_none());
+        assert!(!world.get::<Tile>(goblin_tile).unwrap().blocked);
+        assert_eq!(last_message(&world), "The Goblin dies");
+
+        let xp: Vec<(Entity, i32)> = world
+            .resource::<Events<GainXp>>()
+            .iter_current_update_events()
+            .map(|gain| (gain.target, gain.amount))
+            .collect();
+        assert_eq!(xp, [(player, 10)]);
+        let shocks: Vec<Entity> = world
+            .resource::<Events<MoraleShock>>()
+            .iter_current_update_events()
+            .map(|shock| shock.target)
+            .collect();
+        assert!(shocks.contains(&witness), "the witness loses heart");
+    }
+
+    #[test]
+    fn the_killer_is_recorded_when_the_player_dies() {
+        let (mut world, player, goblin, _) = face_off(3);
+        attack(&mut world, goblin, player);
+
+        assert_eq!(world.get::<CombatStats>(player).unwrap().hp, 0);
+        assert_eq!(world.resource::<Death>().cause, "Killed by a Goblin");
+        assert_eq!(last_message(&world), "You die...");
+        assert!(matches!(
+            *world.resource::<NextState<GameState>>(),
+            NextState::Pending(GameState::GameOver)
+        ));
+    }
+}
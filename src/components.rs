@@ -40,6 +40,21 @@ pub struct Position {
 #[derive(Component)]
 pub struct Player;
 
+/// Turns until the player gets hungrier.
+#[derive(Component)]
+pub struct Hunger {
+    pub state: HungerState,
+    pub turns_left: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HungerState {
+    WellFed,
+    Normal,
+    Hungry,
+    Starving,
+}
+
 #[derive(Component)]
 pub struct Monster {
     pub occupied_tile: Entity,
@@ -47,3 +62,105 @@ pub struct Monster {
 
 #[derive(Component)]
 pub struct Occupied;
+
+/// Something that can be picked up. Its name and glyph are in `Name` and `Renderable`, like
+/// a monster's. Items lying on the map have a `Position`, items in a pack don't.
+#[derive(Component)]
+pub struct Item;
+
+/// An item the player is wearing or holding.
+#[derive(Component)]
+pub struct Equipped;
+
+/// An item the player has seen, so it stays drawn on the map once out of view.
+#[derive(Component)]
+pub struct Remembered;
+
+/// What the player is carrying, in the order it was picked up.
+#[derive(Component)]
+pub struct Inventory {
+    pub items: Vec<Entity>,
+    pub capacity: usize,
+}
+
+/// Energy gained every game tick.
+#[derive(Component)]
+pub struct Speed(pub i32);
+
+/// An actor takes its turn once this reaches `ACTION_THRESHOLD`; acting spends it.
+#[derive(Component)]
+pub struct Energy(pub i32);
+
+/// Anything that can fight and be hurt. Dies when `hp` reaches zero.
+#[derive(Component, Clone, Copy)]
+pub struct CombatStats {
+    pub max_hp: i32,
+    pub hp: i32,
+    pub power: i32,
+    pub defense: i32,
+}
+
+/// The player's level, the xp earned towards the next one, and level-up improvements not
+/// yet picked.
+#[derive(Component)]
+pub struct Experience {
+    pub level: i32,
+    pub xp: i32,
+    pub unspent: i32,
+}
+
+/// Xp awarded to whoever kills this monster.
+#[derive(Component, Clone, Copy)]
+pub struct XpReward(pub i32);
+
+/// What an entity looks like on screen. Only drawn when the game has a window.
+#[derive(Component, Clone, Copy)]
+pub struct Renderable {
+    pub glyph: char,
+    pub color: Color,
+}
+
+/// What a monster is up to. Changed by what it sees and hears.
+#[derive(Component, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AiState {
+    Sleeping,
+    /// Awake and standing at its post.
+    Idle,
+    /// Walking from room to room.
+    Wandering {
+        destination: Option<(usize, usize)>,
+    },
+    /// Going after the player, or the last tile it saw the player on.
+    Hunting {
+        last_seen: (usize, usize),
+    },
+    /// Lost the player around `last_seen` and is looking about for a few more turns.
+    Searching {
+        last_seen: (usize, usize),
+        turns_left: i32,
+    },
+    ReturningToPost,
+    /// Morale broke. Runs from the player until `turns_left` is up.
+    Fleeing {
+        turns_left: i32,
+    },
+}
+
+/// How much a monster can take before it breaks and flees. Runs out at zero.
+#[derive(Component)]
+pub struct Morale {
+    pub current: i32,
+    pub max: i32,
+}
+
+/// Monsters spawned together, named after the first of them. Packmates tell each other
+/// where the player is and spread out around it.
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub struct Pack(pub Entity);
+
+/// Where a monster was put. Guards and sleepers go back here once they lose the player.
+#[derive(Component)]
+pub struct Post {
+    pub x: usize,
+    pub y: usize,
+}
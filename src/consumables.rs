@@ -0,0 +1,441 @@
+use crate::components::*;
+use crate::effects::{Asleep, EffectKind};
+use crate::events::*;
+use crate::gamelog::GameLog;
+use crate::initiative::MOVE_COST;
+use crate::player::{PlayerActionSet, HUNGER_TURNS};
+use crate::random::GameRng;
+use crate::raws::Consumable;
+use crate::resources::*;
+use crate::targeting::tiles_hit;
+use crate::turn::*;
+use bevy::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+
+/// Lets the player use up potions and scrolls. Every consumable is a list of `ItemEffect`s in
+/// the raws, and each effect is settled by its own system here.
+pub struct ConsumablePlugin;
+
+impl Plugin for ConsumablePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<UseItem>()
+            .add_event::<ApplyItemEffect>()
+            .add_systems(
+                Update,
+                (
+                    use_item
+                        .in_set(TurnSet::AwaitingInput)
+                        .in_set(PlayerActionSet),
+                    (heal, feed, hurt, inflict, map_level, teleport)
+                        .chain()
+                        .in_set(TurnSet::AwaitingInput)
+                        .after(PlayerActionSet),
+                ),
+            );
+    }
+}
+
+/// Something a consumable does, as written in the raws.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ItemEffect {
+    Heal { amount: i32 },
+    Feed,
+    Damage { amount: i32 },
+    Inflict { effect: EffectKind, turns: i32 },
+    MagicMapping,
+    Teleport,
+}
+
+/// Takes the turn if the item got used.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn use_item(
+    mut commands: Commands,
+    mut events: EventReader<UseItem>,
+    mut query_player: Query<
+        (Entity, &Position, &mut Inventory, &mut Energy, Has<Asleep>),
+        With<Player>,
+    >,
+    query_items: Query<(&Name, Option<&Consumable>), With<Item>>,
+    query_creatures: Query<(Entity, &Position), With<CombatStats>>,
+    query_tiles: Query<&Tile>,
+    map: Res<Map>,
+    mut effects: EventWriter<ApplyItemEffect>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+    mut next_state: ResMut<NextState<TurnState>>,
+) {
+    let Some(UseItem { item, target }) = events.read().last().copied() else {
+        return;
+    };
+    let (player, player_pos, mut inventory, mut energy, asleep) = query_player.single_mut();
+    if asleep {
+        return;
+    }
+    let Some(idx) = inventory.items.iter().position(|&carried| carried == item) else {
+        return;
+    };
+    let Ok((name, consumable)) = query_items.get(item) else {
+        return;
+    };
+    let Some(consumable) = consumable else {
+        log.add(
+            turn_count.0,
+            "You can't use that",
+            Color::srgb(0.5, 0.5, 0.5),
+        );
+        return;
+    };
+
+    let targets: Vec<Entity> = match (consumable.aim, target) {
+        (None, _) => vec![player],
+        (Some(aim), Some(target)) => {
+            let hits = tiles_hit(
+                &map,
+                &query_tiles,
+                (player_pos.x, player_pos.y),
+                target,
+                aim,
+            );
+            // Whoever throws it knows to keep out of its way.
+            query_creatures
+                .iter()
+                .filter(|&(creature, position)| {
+                    creature != player && hits.contains(&(position.x, position.y))
+                })
+                .map(|(creature, _)| creature)
+                .collect()
+        }
+        (Some(_), None) => {
+            log.add(
+                turn_count.0,
+                format!("The {name} has to be aimed"),
+                Color::srgb(0.5, 0.5, 0.5),
+            );
+            return;
+        }
+    };
+
+    log.add(turn_count.0, format!("You use the {name}"), Color::WHITE);
+    for &effect in consumable.effects.iter() {
+        for &target in targets.iter() {
+            effects.send(ApplyItemEffect {
+                user: player,
+                target,
+                effect,
+            });
+        }
+    }
+    inventory.items.remove(idx);
+    commands.entity(item).despawn();
+    energy.0 -= MOVE_COST;
+    next_state.set(TurnState::PlayerTurn);
+}
+
+fn heal(
+    mut events: EventReader<ApplyItemEffect>,
+    mut query_stats: Query<(&mut CombatStats, &Name, Has<Player>)>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+) {
+    for event in events.read() {
+        let ItemEffect::Heal { amount } = event.effect else {
+            continue;
+        };
+        let Ok((mut stats, name, is_player)) = query_stats.get_mut(event.target) else {
+            continue;
+        };
+        stats.hp = (stats.hp + amount).min(stats.max_hp);
+        let message = if is_player {
+            "You feel better".to_string()
+        } else {
+            format!("The {name} looks better")
+        };
+        log.add(turn_count.0, message, Color::srgb(0.0, 1.0, 0.0));
+    }
+}
+
+fn feed(
+    mut events: EventReader<ApplyItemEffect>,
+    mut query_hunger: Query<&mut Hunger>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+) {
+    for event in events.read() {
+        if event.effect != ItemEffect::Feed {
+            continue;
+        }
+        let Ok(mut hunger) = query_hunger.get_mut(event.target) else {
+            continue;
+        };
+        hunger.state = HungerState::WellFed;
+        hunger.turns_left = HUNGER_TURNS;
+        log.add(turn_count.0, "You feel full", Color::srgb(0.0, 1.0, 0.0));
+    }
+}
+
+/// Hurts through the usual damage pipeline, so kills still earn the user xp.
+fn hurt(
+    mut events: EventReader<ApplyItemEffect>,
+    query_names: Query<(&Name, Has<Player>)>,
+    mut damage: EventWriter<Damage>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+) {
+    for event in events.read() {
+        let ItemEffect::Damage { amount } = event.effect else {
+            continue;
+        };
+        let Ok((name, is_player)) = query_names.get(event.target) else {
+            continue;
+        };
+        let message = if is_player {
+            format!("You take {amount} damage")
+        } else {
+            format!("The {name} takes {amount} damage")
+        };
+        log.add(turn_count.0, message, Color::srgb(1.0, 0.5, 0.0));
+        damage.send(Damage {
+            source: event.user,
+            target: event.target,
+            amount,
+        });
+    }
+}
+
+fn inflict(
+    mut commands: Commands,
+    mut events: EventReader<ApplyItemEffect>,
+    query_names: Query<(&Name, Has<Player>)>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+) {
+    for event in events.read() {
+        let ItemEffect::Inflict { effect, turns } = event.effect else {
+            continue;
+        };
+        let Ok((name, is_player)) = query_names.get(event.target) else {
+            continue;
+        };
+        effect.apply(&mut commands, event.target, event.user, turns);
+        let message = if is_player {
+            format!("You are {}", effect.describe())
+        } else {
+            format!("The {name} is {}", effect.describe())
+        };
+        log.add(turn_count.0, message, Color::srgb(1.0, 0.0, 1.0));
+    }
+}
+
+/// Every tile on the level becomes remembered, as if the player had already been there.
+fn map_level(
+    mut events: EventReader<ApplyItemEffect>,
+    mut query_tiles: Query<&mut Tile>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+) {
+    if !events
+        .read()
+        .any(|event| event.effect == ItemEffect::MagicMapping)
+    {
+        return;
+    }
+    for mut tile in query_tiles.iter_mut() {
+        if tile.visibletype == VisibleType::Invisible {
+            tile.visibletype = VisibleType::Memoried;
+        }
+    }
+    log.add(
+        turn_count.0,
+        "The layout of the level comes to you",
+        Color::srgb(0.0, 1.0, 1.0),
+    );
+}
+
+/// Moves the player to a random open floor tile somewhere on the level.
+fn teleport(
+    mut events: EventReader<ApplyItemEffect>,
+    mut query_player: Query<(&mut Position, &mut Transform), With<Player>>,
+    query_tiles: Query<(&Tile, &Position, &Transform), Without<Player>>,
+    map: Res<Map>,
+    mut rng: ResMut<GameRng>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+) {
+    for event in events.read() {
+        if event.effect != ItemEffect::Teleport {
+            continue;
+        }
+        let Ok((mut position, mut transform)) = query_player.get_mut(event.target) else {
+            continue;
+        };
+        let open: Vec<(&Position, &Transform)> = map
+            .tiles
+            .iter()
+            .filter_map(|&tile| query_tiles.get(tile).ok())
+            .filter(|(tile, tile_pos, _)| {
+                tile.tiletype == TileType::Floor
+                    && !tile.blocked
+                    && (tile_pos.x, tile_pos.y) != (position.x, position.y)
+            })
+            .map(|(_, tile_pos, tile_transform)| (tile_pos, tile_transform))
+            .collect();
+        if open.is_empty() {
+            continue;
+        }
+        let (tile_pos, tile_transform) = open[rng.gen_range(0..open.len())];
+        position.x = tile_pos.x;
+        position.y = tile_pos.y;
+        transform.translation.x = tile_transform.translation.x;
+        transform.translation.y = tile_transform.translation.y;
+        log.add(
+            turn_count.0,
+            "You find yourself somewhere else",
+            Color::srgb(0.5, 0.5, 1.0),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::Confused;
+    use crate::map::testing::{block, test_world};
+    use crate::raws::{load_raws, ItemRaws, ITEM_RAWS};
+    use bevy::ecs::schedule::Schedule;
+
+    const ROOM: [&str; 5] = [
+        "##########",
+        "#........#",
+        "#........#",
+        "#........#",
+        "##########",
+    ];
+
+    /// The player at (2, 2) carrying `item`, with a Goblin at (4, 2).
+    fn carrying(item: &str) -> (World, Entity, Entity, Entity) {
+        let mut world = test_world(&ROOM);
+        world.init_resource::<Events<UseItem>>();
+        world.init_resource::<Events<ApplyItemEffect>>();
+        world.init_resource::<Events<Damage>>();
+        world.init_resource::<TurnCount>();
+        world.init_resource::<GameLog>();
+        world.init_resource::<NextState<TurnState>>();
+        world.insert_resource(GameRng::from_seed(1));
+        for tile in world.resource::<Map>().tiles.clone() {
+            world.entity_mut(tile).insert(Transform::default());
+        }
+
+        let raws: ItemRaws = load_raws(ITEM_RAWS);
+        let template = raws.template(item).unwrap();
+        let item = world
+            .spawn((
+                Item,
+                Name::new(template.name.clone()),
+                template.consumable.clone().unwrap(),
+            ))
+            .id();
+        let player = world
+            .spawn((
+                Player,
+                Name::new("Player"),
+                Position { x: 2, y: 2 },
+                Transform::default(),
+                CombatStats {
+                    max_hp: 30,
+                    hp: 10,
+                    power: 5,
+                    defense: 2,
+                },
+                Inventory {
+                    items: vec![item],
+                    capacity: 10,
+                },
+                Energy(0),
+            ))
+            .id();
+        let goblin = world
+            .spawn((
+                Name::new("Goblin"),
+                Position { x: 4, y: 2 },
+                CombatStats {
+                    max_hp: 8,
+                    hp: 8,
+                    power: 3,
+                    defense: 1,
+                },
+            ))
+            .id();
+        block(&mut world, (2, 2));
+        block(&mut world, (4, 2));
+        (world, player, goblin, item)
+    }
+
+    fn use_on(world: &mut World, item: Entity, target: Option<(usize, usize)>) {
+        world.send_event(UseItem { item, target });
+        let mut schedule = Schedule::default();
+        schedule.add_systems((use_item, (heal, feed, hurt, inflict, map_level, teleport)).chain());
+        schedule.run(world);
+    }
+
+    fn used_up(world: &World, player: Entity, item: Entity) -> bool {
+        world.get::<Inventory>(player).unwrap().items.is_empty()
+            && world.get_entity(item).is_none()
+            && world.get::<Energy>(player).unwrap().0 == -MOVE_COST
+    }
+
+    #[test]
+    fn healing_stops_at_max_hp() {
+        let (mut world, player, _, item) = carrying("Health Potion");
+        use_on(&mut world, item, None);
+        assert_eq!(world.get::<CombatStats>(player).unwrap().hp, 25);
+        assert!(used_up(&world, player, item));
+
+        let (mut world, player, _, item) = carrying("Health Potion");
+        world.get_mut::<CombatStats>(player).unwrap().hp = 28;
+        use_on(&mut world, item, None);
+        assert_eq!(world.get::<CombatStats>(player).unwrap().hp, 30);
+    }
+
+    #[test]
+    fn a_fireball_spares_whoever_threw_it() {
+        let (mut world, player, goblin, item) = carrying("Scroll of Fireball");
+        use_on(&mut world, item, Some((4, 2)));
+        let hurt: Vec<(Entity, Entity, i32)> = world
+            .resource::<Events<Damage>>()
+            .iter_current_update_events()
+            .map(|damage| (damage.source, damage.target, damage.amount))
+            .collect();
+        assert_eq!(hurt, [(player, goblin, 8)]);
+        assert!(used_up(&world, player, item));
+    }
+
+    #[test]
+    fn aimed_items_need_a_target() {
+        let (mut world, player, _, item) = carrying("Scroll of Fireball");
+        use_on(&mut world, item, None);
+        assert_eq!(world.get::<Inventory>(player).unwrap().items, [item]);
+        assert_eq!(world.get::<Energy>(player).unwrap().0, 0);
+    }
+
+    #[test]
+    fn confusion_lands_on_the_target() {
+        let (mut world, player, goblin, item) = carrying("Scroll of Confusion");
+        use_on(&mut world, item, Some((4, 2)));
+        assert_eq!(world.get::<Confused>(goblin).unwrap().turns_left, 6);
+        assert!(world.get::<Confused>(player).is_none());
+        assert!(used_up(&world, player, item));
+    }
+
+    #[test]
+    fn teleporting_lands_on_open_floor() {
+        let (mut world, player, _, item) = carrying("Scroll of Teleportation");
+        use_on(&mut world, item, None);
+        let position = world.get::<Position>(player).unwrap();
+        let landed = (position.x, position.y);
+        assert_ne!(landed, (2, 2));
+        assert_ne!(landed, (4, 2), "not on top of the goblin");
+        assert!((1..=8).contains(&landed.0) && (1..=3).contains(&landed.1));
+        assert!(used_up(&world, player, item));
+    }
+}
@@ -0,0 +1,98 @@
+use crate::components::*;
+use crate::glyphs::GlyphFont;
+use bevy::prelude::*;
+
+/// F1 shows what every monster is up to, hidden or not.
+pub struct AiDebugPlugin;
+
+impl Plugin for AiDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ShowAiStates>()
+            .add_systems(Update, (toggle_ai_states, show_ai_states).chain());
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ShowAiStates(pub bool);
+
+#[derive(Component)]
+pub struct AiStateLabel(pub Entity);
+
+fn toggle_ai_states(keys: Res<ButtonInput<KeyCode>>, mut show: ResMut<ShowAiStates>) {
+    if keys.just_pressed(KeyCode::F1) {
+        show.0 = !show.0;
+    }
+}
+
+fn show_ai_states(
+    mut commands: Commands,
+    show: Res<ShowAiStates>,
+    glyph_font: Res<GlyphFont>,
+    query_monsters: Query<(Entity, &AiState, &Transform), With<Monster>>,
+    mut query_labels: Query<
+        (
+            Entity,
+            &AiStateLabel,
+            &mut Text,
+            &mut Transform,
+            &mut Visibility,
+        ),
+        Without<Monster>,
+    >,
+) {
+    let mut labelled = Vec::new();
+    for (ent, label, mut text, mut transform, mut visibility) in query_labels.iter_mut() {
+        let Ok((_monster, state, monster_transform)) = query_monsters.get(label.0) else {
+            commands.entity(ent).despawn();
+            continue;
+        };
+        text.sections[0].value = state_label(state).to_string();
+        transform.translation.x = monster_transform.translation.x;
+        transform.translation.y = monster_transform.translation.y + glyph_font.font_size;
+        *visibility = if show.0 {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+        labelled.push(label.0);
+    }
+
+    for (monster, state, transform) in query_monsters.iter() {
+        if labelled.contains(&monster) {
+            continue;
+        }
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    state_label(state),
+                    TextStyle {
+                        font: glyph_font.font.clone(),
+                        font_size: glyph_font.font_size,
+                        color: Color::srgb(1.0, 1.0, 0.0),
+                    },
+                )
+                .with_justify(JustifyText::Center),
+                transform: Transform::from_xyz(
+                    transform.translation.x,
+                    transform.translation.y + glyph_font.font_size,
+                    3.0,
+                ),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+            AiStateLabel(monster),
+        ));
+    }
+}
+
+fn state_label(state: &AiState) -> &'static str {
+    match state {
+        AiState::Sleeping => "z",
+        AiState::Idle => ".",
+        AiState::Wandering { .. } => "~",
+        AiState::Hunting { .. } => "!",
+        AiState::Searching { .. } => "?",
+        AiState::ReturningToPost => "<",
+        AiState::Fleeing { .. } => "*",
+    }
+}
@@ -0,0 +1,191 @@
+use crate::components::*;
+use crate::map::get_tile_idx;
+use crate::pathfinding::offset;
+use crate::resources::*;
+use crate::turn::*;
+use bevy::ecs::query::QueryFilter;
+use bevy::prelude::*;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+pub const UNREACHABLE: i32 = i32::MAX;
+
+const STEP_COST: i32 = 10;
+
+pub struct DijkstraPlugin;
+
+impl Plugin for DijkstraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FlowMaps>()
+            .add_systems(Update, update_flow_maps.in_set(TurnSet::PlayerTurn));
+    }
+}
+
+/// Distance from every floor tile to the nearest source, in tenths of a step.
+pub struct DijkstraMap {
+    pub values: Vec<i32>,
+}
+
+impl DijkstraMap {
+    pub fn new<F: QueryFilter>(
+        map: &Map,
+        query_tiles: &Query<&Tile, F>,
+        sources: &[(usize, usize)],
+    ) -> Self {
+        let mut dijkstra = DijkstraMap {
+            values: vec![UNREACHABLE; map.tiles.len()],
+        };
+        for &(x, y) in sources {
+            dijkstra.values[get_tile_idx(x, y)] = 0;
+        }
+        dijkstra.relax(map, query_tiles);
+        dijkstra
+    }
+
+    /// A map that leads away from the sources of `self`. Distances are flipped and scaled
+    /// so fleeing prefers getting far away over the nearest dead end, then smoothed again.
+    pub fn flee<F: QueryFilter>(&self, map: &Map, query_tiles: &Query<&Tile, F>) -> Self {
+        let mut dijkstra = DijkstraMap {
+            values: self
+                .values
+                .iter()
+                .map(|&value| {
+                    if value == UNREACHABLE {
+                        UNREACHABLE
+                    } else {
+                        value * -12 / 10
+                    }
+                })
+                .collect(),
+        };
+        dijkstra.relax(map, query_tiles);
+        dijkstra
+    }
+
+    pub fn get(&self, (x, y): (usize, usize)) -> i32 {
+        self.values[get_tile_idx(x, y)]
+    }
+
+    /// The neighbouring tile that goes downhill the most, skipping anything occupied.
+    pub fn best_next_step<F: QueryFilter>(
+        &self,
+        map: &Map,
+        query_tiles: &Query<&Tile, F>,
+        from: (usize, usize),
+    ) -> Option<(usize, usize)> {
+        let mut best = None;
+        let mut best_value = self.get(from);
+        for next in floor_neighbours(map, query_tiles, from) {
+            let free = query_tiles
+                .get(map.tiles[get_tile_idx(next.0, next.1)])
+                .is_ok_and(|tile| !tile.blocked);
+            if free && self.get(next) < best_value {
+                best = Some(next);
+                best_value = self.get(next);
+            }
+        }
+        best
+    }
+
+    fn relax<F: QueryFilter>(&mut self, map: &Map, query_tiles: &Query<&Tile, F>) {
+        let mut open = BinaryHeap::new();
+        for y in 0..map.tile_res.height {
+            for x in 0..map.tile_res.width {
+                let value = self.values[get_tile_idx(x, y)];
+                if value != UNREACHABLE {
+                    open.push(Reverse((value, (x, y))));
+                }
+            }
+        }
+
+        while let Some(Reverse((value, current))) = open.pop() {
+            if value > self.get(current) {
+                continue;
+            }
+            for next in floor_neighbours(map, query_tiles, current) {
+                let idx = get_tile_idx(next.0, next.1);
+                if value + STEP_COST < self.values[idx] {
+                    self.values[idx] = value + STEP_COST;
+                    open.push(Reverse((value + STEP_COST, next)));
+                }
+            }
+        }
+    }
+}
+
+/// Flow maps shared by everyone, rebuilt when the player or an item moves, or the layout
+/// changes.
+#[derive(Resource, Default)]
+pub struct FlowMaps {
+    pub approach_player: Option<DijkstraMap>,
+    pub flee_player: Option<DijkstraMap>,
+    /// Leads to the nearest item lying on the map. `None` while there aren't any.
+    pub seek_items: Option<DijkstraMap>,
+    pub player_pos: Option<(usize, usize)>,
+    pub item_tiles: Vec<(usize, usize)>,
+    /// The `TerrainRevision` the maps were built for.
+    pub terrain: Option<u32>,
+}
+
+fn update_flow_maps(
+    mut flow_maps: ResMut<FlowMaps>,
+    query_player: Query<&Position, With<Player>>,
+    query_items: Query<&Position, With<Item>>,
+    query_tiles: Query<&Tile>,
+    terrain: Res<TerrainRevision>,
+    map: Res<Map>,
+) {
+    let player_pos = query_player.single();
+    let player_pos = (player_pos.x, player_pos.y);
+    let mut item_tiles: Vec<(usize, usize)> = query_items
+        .iter()
+        .map(|position| (position.x, position.y))
+        .collect();
+    item_tiles.sort();
+    let terrain_changed = flow_maps.terrain != Some(terrain.0);
+    flow_maps.terrain = Some(terrain.0);
+
+    if terrain_changed || flow_maps.player_pos != Some(player_pos) {
+        let approach = DijkstraMap::new(&map, &query_tiles, &[player_pos]);
+        flow_maps.flee_player = Some(approach.flee(&map, &query_tiles));
+        flow_maps.approach_player = Some(approach);
+        flow_maps.player_pos = Some(player_pos);
+    }
+    if terrain_changed || flow_maps.item_tiles != item_tiles {
+        flow_maps.seek_items =
+            (!item_tiles.is_empty()).then(|| DijkstraMap::new(&map, &query_tiles, &item_tiles));
+        flow_maps.item_tiles = item_tiles;
+    }
+}
+
+/// Floor tiles next to `from`, not cutting across wall corners.
+fn floor_neighbours<F: QueryFilter>(
+    map: &Map,
+    query_tiles: &Query<&Tile, F>,
+    from: (usize, usize),
+) -> Vec<(usize, usize)> {
+    let is_floor = |(x, y): (usize, usize)| {
+        query_tiles
+            .get(map.tiles[get_tile_idx(x, y)])
+            .is_ok_and(|tile| tile.tiletype == TileType::Floor)
+    };
+    let mut tiles = Vec::new();
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            if dx == 0 && dy == 0 {
+                continue;
+            }
+            let Some(next) = offset(map, from, dx, dy) else {
+                continue;
+            };
+            if !is_floor(next) {
+                continue;
+            }
+            if dx != 0 && dy != 0 && (!is_floor((next.0, from.1)) || !is_floor((from.0, next.1))) {
+                continue;
+            }
+            tiles.push(next);
+        }
+    }
+    tiles
+}
@@ -0,0 +1,326 @@
+use crate::events::*;
+use crate::turn::*;
+use bevy::ecs::query::QueryData;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+/// Ticks down the status effects on the player and monsters once a turn.
+pub struct StatusEffectsPlugin;
+
+impl Plugin for StatusEffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                hurt_poisoned,
+                tick_effect::<Poisoned>,
+                tick_effect::<Confused>,
+                tick_effect::<Asleep>,
+                tick_effect::<Hasted>,
+                tick_effect::<Slowed>,
+                tick_effect::<Blinded>,
+            )
+                .chain()
+                .in_set(TurnSet::PlayerTurn),
+        );
+    }
+}
+
+/// Something wearing off after a number of turns. Getting the same effect again refreshes
+/// how long it lasts, unless the effect says it stacks.
+pub trait StatusEffect: Component + Sized {
+    fn turns_left(&mut self) -> &mut i32;
+
+    fn stack(&mut self, mut new: Self) {
+        let turns = (*self.turns_left()).max(*new.turns_left());
+        *self.turns_left() = turns;
+    }
+}
+
+/// Loses `damage` hp every turn. Another dose adds to the damage.
+#[derive(Component)]
+pub struct Poisoned {
+    pub turns_left: i32,
+    pub damage: i32,
+    pub source: Entity,
+}
+
+/// Stumbles about in random directions.
+#[derive(Component)]
+pub struct Confused {
+    pub turns_left: i32,
+}
+
+/// Loses its turns.
+#[derive(Component)]
+pub struct Asleep {
+    pub turns_left: i32,
+}
+
+/// Twice as fast.
+#[derive(Component)]
+pub struct Hasted {
+    pub turns_left: i32,
+}
+
+/// Half as fast.
+#[derive(Component)]
+pub struct Slowed {
+    pub turns_left: i32,
+}
+
+/// Can't see a thing.
+#[derive(Component)]
+pub struct Blinded {
+    pub turns_left: i32,
+}
+
+impl StatusEffect for Poisoned {
+    fn turns_left(&mut self) -> &mut i32 {
+        &mut self.turns_left
+    }
+
+    fn stack(&mut self, new: Self) {
+        self.turns_left = self.turns_left.max(new.turns_left);
+        self.damage += new.damage;
+        self.source = new.source;
+    }
+}
+
+impl StatusEffect for Confused {
+    fn turns_left(&mut self) -> &mut i32 {
+        &mut self.turns_left
+    }
+}
+
+impl StatusEffect for Asleep {
+    fn turns_left(&mut self) -> &mut i32 {
+        &mut self.turns_left
+    }
+}
+
+impl StatusEffect for Hasted {
+    fn turns_left(&mut self) -> &mut i32 {
+        &mut self.turns_left
+    }
+}
+
+impl StatusEffect for Slowed {
+    fn turns_left(&mut self) -> &mut i32 {
+        &mut self.turns_left
+    }
+}
+
+impl StatusEffect for Blinded {
+    fn turns_left(&mut self) -> &mut i32 {
+        &mut self.turns_left
+    }
+}
+
+/// Puts `effect` on `target`, stacking it with one that's already there.
+pub fn apply_effect<E: StatusEffect>(commands: &mut Commands, target: Entity, effect: E) {
+    commands
+        .entity(target)
+        .add(move |mut entity: EntityWorldMut| {
+            if let Some(mut existing) = entity.get_mut::<E>() {
+                existing.stack(effect);
+            } else {
+                entity.insert(effect);
+            }
+        });
+}
+
+/// An effect as written in the raws.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EffectKind {
+    Poison { damage: i32 },
+    Confusion,
+    Sleep,
+    Haste,
+    Slow,
+    Blindness,
+}
+
+impl EffectKind {
+    pub fn apply(self, commands: &mut Commands, target: Entity, source: Entity, turns_left: i32) {
+        match self {
+            EffectKind::Poison { damage } => apply_effect(
+                commands,
+                target,
+                Poisoned {
+                    turns_left,
+                    damage,
+                    source,
+                },
+            ),
+            EffectKind::Confusion => apply_effect(commands, target, Confused { turns_left }),
+            EffectKind::Sleep => apply_effect(commands, target, Asleep { turns_left }),
+            // Haste and slow cancel each other out.
+            EffectKind::Haste => {
+                commands.entity(target).remove::<Slowed>();
+                apply_effect(commands, target, Hasted { turns_left });
+            }
+            EffectKind::Slow => {
+                commands.entity(target).remove::<Hasted>();
+                apply_effect(commands, target, Slowed { turns_left });
+            }
+            EffectKind::Blindness => apply_effect(commands, target, Blinded { turns_left }),
+        }
+    }
+
+    /// How the effect reads in "You are ..." messages.
+    pub fn describe(self) -> &'static str {
+        match self {
+            EffectKind::Poison { .. } => "poisoned",
+            EffectKind::Confusion => "confused",
+            EffectKind::Sleep => "put to sleep",
+            EffectKind::Haste => "sped up",
+            EffectKind::Slow => "slowed down",
+            EffectKind::Blindness => "blinded",
+        }
+    }
+}
+
+/// Every status effect an entity might have, for whatever needs to look at all of them.
+#[derive(QueryData)]
+pub struct Effects {
+    pub poisoned: Option<Ref<'static, Poisoned>>,
+    pub confused: Option<Ref<'static, Confused>>,
+    pub asleep: Option<Ref<'static, Asleep>>,
+    pub hasted: Option<Ref<'static, Hasted>>,
+    pub slowed: Option<Ref<'static, Slowed>>,
+    pub blinded: Option<Ref<'static, Blinded>>,
+}
+
+impl EffectsItem<'_> {
+    pub fn is_changed(&self) -> bool {
+        self.poisoned.as_ref().is_some_and(|e| e.is_changed())
+            || self.confused.as_ref().is_some_and(|e| e.is_changed())
+            || self.asleep.as_ref().is_some_and(|e| e.is_changed())
+            || self.hasted.as_ref().is_some_and(|e| e.is_changed())
+            || self.slowed.as_ref().is_some_and(|e| e.is_changed())
+            || self.blinded.as_ref().is_some_and(|e| e.is_changed())
+    }
+
+    /// Names of the effects that are on, with the colour to show each in.
+    pub fn labels(&self) -> Vec<(&'static str, Color)> {
+        [
+            (
+                self.poisoned.is_some(),
+                "Poisoned",
+                Color::srgb(0.0, 0.8, 0.0),
+            ),
+            (
+                self.confused.is_some(),
+                "Confused",
+                Color::srgb(1.0, 0.0, 1.0),
+            ),
+            (self.asleep.is_some(), "Asleep", Color::srgb(0.5, 0.5, 1.0)),
+            (self.hasted.is_some(), "Hasted", Color::srgb(0.0, 1.0, 1.0)),
+            (self.slowed.is_some(), "Slowed", Color::srgb(0.6, 0.6, 0.6)),
+            (self.blinded.is_some(), "Blind", Color::srgb(0.5, 0.5, 0.5)),
+        ]
+        .into_iter()
+        .filter(|(on, _, _)| *on)
+        .map(|(_, label, color)| (label, color))
+        .collect()
+    }
+}
+
+/// How fast an actor with these effects really is.
+pub fn effective_speed(speed: i32, hasted: bool, slowed: bool) -> i32 {
+    match (hasted, slowed) {
+        (true, false) => speed * 2,
+        (false, true) => (speed / 2).max(1),
+        _ => speed,
+    }
+}
+
+fn hurt_poisoned(query_poisoned: Query<(Entity, &Poisoned)>, mut damage: EventWriter<Damage>) {
+    for (target, poisoned) in query_poisoned.iter() {
+        damage.send(Damage {
+            source: poisoned.source,
+            target,
+            amount: poisoned.damage,
+        });
+    }
+}
+
+fn tick_effect<E: StatusEffect>(
+    mut commands: Commands,
+    mut query_effects: Query<(Entity, &mut E)>,
+) {
+    for (ent, mut effect) in query_effects.iter_mut() {
+        *effect.turns_left() -= 1;
+        if *effect.turns_left() <= 0 {
+            commands.entity(ent).remove::<E>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::schedule::Schedule;
+
+    fn run<M>(world: &mut World, systems: impl IntoSystemConfigs<M>) {
+        let mut schedule = Schedule::default();
+        schedule.add_systems(systems);
+        schedule.run(world);
+    }
+
+    fn inflict(world: &mut World, target: Entity, effect: EffectKind, turns: i32) {
+        run(world, move |mut commands: Commands| {
+            effect.apply(&mut commands, target, target, turns)
+        });
+    }
+
+    #[test]
+    fn poison_stacks_its_damage() {
+        let mut world = World::new();
+        let target = world.spawn_empty().id();
+        inflict(&mut world, target, EffectKind::Poison { damage: 2 }, 5);
+        inflict(&mut world, target, EffectKind::Poison { damage: 3 }, 3);
+        let poisoned = world.get::<Poisoned>(target).unwrap();
+        assert_eq!((poisoned.damage, poisoned.turns_left), (5, 5));
+    }
+
+    #[test]
+    fn other_effects_refresh_how_long_they_last() {
+        let mut world = World::new();
+        let target = world.spawn_empty().id();
+        inflict(&mut world, target, EffectKind::Confusion, 3);
+        inflict(&mut world, target, EffectKind::Confusion, 6);
+        assert_eq!(world.get::<Confused>(target).unwrap().turns_left, 6);
+        inflict(&mut world, target, EffectKind::Confusion, 2);
+        assert_eq!(world.get::<Confused>(target).unwrap().turns_left, 6);
+    }
+
+    #[test]
+    fn haste_and_slow_cancel_out() {
+        let mut world = World::new();
+        let target = world.spawn_empty().id();
+        inflict(&mut world, target, EffectKind::Haste, 4);
+        inflict(&mut world, target, EffectKind::Slow, 4);
+        assert!(world.get::<Hasted>(target).is_none());
+        assert!(world.get::<Slowed>(target).is_some());
+    }
+
+    #[test]
+    fn effects_wear_off() {
+        let mut world = World::new();
+        let target = world.spawn(Asleep { turns_left: 2 }).id();
+        run(&mut world, tick_effect::<Asleep>);
+        assert_eq!(world.get::<Asleep>(target).unwrap().turns_left, 1);
+        run(&mut world, tick_effect::<Asleep>);
+        assert!(world.get::<Asleep>(target).is_none());
+    }
+
+    #[test]
+    fn speed_never_drops_below_one() {
+        assert_eq!(effective_speed(10, true, false), 20);
+        assert_eq!(effective_speed(10, false, true), 5);
+        assert_eq!(effective_speed(1, false, true), 1);
+        assert_eq!(effective_speed(10, true, true), 10);
+    }
+}
@@ -0,0 +1,268 @@
+use crate::components::*;
+use crate::effects::Asleep;
+use crate::events::*;
+use crate::gamelog::GameLog;
+use crate::initiative::MOVE_COST;
+use crate::player::{PlayerActionSet, PLAYER_SIGHT};
+use crate::raws::Equipment;
+use crate::resources::*;
+use crate::turn::*;
+use bevy::prelude::*;
+
+/// Lets the player wear weapons, armor and lights from their pack. What's worn adds to the
+/// stats combat uses, and a light sets how far the player sees.
+pub struct EquipmentPlugin;
+
+impl Plugin for EquipmentPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Equip>().add_event::<Unequip>().add_systems(
+            Update,
+            (put_on, take_off)
+                .in_set(TurnSet::AwaitingInput)
+                .in_set(PlayerActionSet),
+        );
+    }
+}
+
+/// Takes the turn if something was put on.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn put_on(
+    mut commands: Commands,
+    mut events: EventReader<Equip>,
+    mut query_player: Query<
+        (
+            &Inventory,
+            &mut CombatStats,
+            &mut Viewshed,
+            &mut Energy,
+            Has<Asleep>,
+        ),
+        With<Player>,
+    >,
+    query_items: Query<(&Name, Option<&Equipment>, Has<Equipped>), With<Item>>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+    mut next_state: ResMut<NextState<TurnState>>,
+) {
+    let Some(Equip(item)) = events.read().last().copied() else {
+        return;
+    };
+    let (inventory, mut stats, mut viewshed, mut energy, asleep) = query_player.single_mut();
+    if asleep || !inventory.items.contains(&item) {
+        return;
+    }
+    let Ok((name, equipment, worn)) = query_items.get(item) else {
+        return;
+    };
+    let Some(equipment) = equipment else {
+        log.add(
+            turn_count.0,
+            "You can't wear that",
+            Color::srgb(0.5, 0.5, 0.5),
+        );
+        return;
+    };
+    if worn {
+        return;
+    }
+
+    for &other in inventory.items.iter() {
+        let Ok((other_name, Some(other_equipment), true)) = query_items.get(other) else {
+            continue;
+        };
+        if other_equipment.slot != equipment.slot {
+            continue;
+        }
+        remove_bonuses(other_equipment, &mut stats, &mut viewshed);
+        commands.entity(other).remove::<Equipped>();
+        log.add(
+            turn_count.0,
+            format!("You take off the {other_name}"),
+            Color::WHITE,
+        );
+    }
+    add_bonuses(equipment, &mut stats, &mut viewshed);
+    commands.entity(item).insert(Equipped);
+    log.add(turn_count.0, format!("You equip the {name}"), Color::WHITE);
+    energy.0 -= MOVE_COST;
+    next_state.set(TurnState::PlayerTurn);
+}
+
+/// Takes the turn if something was taken off.
+#[allow(clippy::type_complexity)]
+fn take_off(
+    mut commands: Commands,
+    mut events: EventReader<Unequip>,
+    mut query_player: Query<
+        (&mut CombatStats, &mut Viewshed, &mut Energy, Has<Asleep>),
+        With<Player>,
+    >,
+    query_items: Query<(&Name, &Equipment), (With<Item>, With<Equipped>)>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+    mut next_state: ResMut<NextState<TurnState>>,
+) {
+    let Some(Unequip(item)) = events.read().last().copied() else {
+        return;
+    };
+    let (mut stats, mut viewshed, mut energy, asleep) = query_player.single_mut();
+    if asleep {
+        return;
+    }
+    let Ok((name, equipment)) = query_items.get(item) else {
+        return;
+    };
+
+    remove_bonuses(equipment, &mut stats, &mut viewshed);
+    commands.entity(item).remove::<Equipped>();
+    log.add(
+        turn_count.0,
+        format!("You take off the {name}"),
+        Color::WHITE,
+    );
+    energy.0 -= MOVE_COST;
+    next_state.set(TurnState::PlayerTurn);
+}
+
+fn add_bonuses(equipment: &Equipment, stats: &mut CombatStats, viewshed: &mut Viewshed) {
+    stats.power += equipment.power;
+    stats.defense += equipment.defense;
+    if let Some(light) = equipment.light {
+        viewshed.range = light;
+    }
+}
+
+fn remove_bonuses(equipment: &Equipment, stats: &mut CombatStats, viewshed: &mut Viewshed) {
+    stats.power -= equipment.power;
+    stats.defense -= equipment.defense;
+    if equipment.light.is_some() {
+        viewshed.range = PLAYER_SIGHT;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raws::{load_raws, ItemRaws, ITEM_RAWS};
+    use bevy::ecs::schedule::Schedule;
+
+    const STATS: CombatStats = CombatStats {
+        max_hp: 30,
+        hp: 30,
+        power: 5,
+        defense: 2,
+    };
+
+    /// A player carrying one of each of `items`, wearing nothing.
+    fn carrying(items: &[&str]) -> (World, Entity, Vec<Entity>) {
+        let mut world = World::new();
+        world.init_resource::<Events<Equip>>();
+        world.init_resource::<Events<Unequip>>();
+        world.init_resource::<TurnCount>();
+        world.init_resource::<GameLog>();
+        world.init_resource::<NextState<TurnState>>();
+        let raws: ItemRaws = load_raws(ITEM_RAWS);
+        let items: Vec<Entity> = items
+            .iter()
+            .map(|&name| {
+                let template = raws.template(name).unwrap();
+                world
+                    .spawn((
+                        Item,
+                        Name::new(template.name.clone()),
+                        template.equipment.unwrap(),
+                    ))
+                    .id()
+            })
+            .collect();
+        let player = world
+            .spawn((
+                Player,
+                Inventory {
+                    items: items.clone(),
+                    capacity: 10,
+                },
+                STATS,
+                Viewshed {
+                    visible_tiles: Vec::new(),
+                    range: PLAYER_SIGHT,
+                },
+                Energy(0),
+            ))
+            .id();
+        (world, player, items)
+    }
+
+    /// Runs both systems once. Events are cleared afterwards, since a fresh schedule would
+    /// read old ones again.
+    fn run(world: &mut World) {
+        let mut schedule = Schedule::default();
+        schedule.add_systems((put_on, take_off).chain());
+        schedule.run(world);
+        world.resource_mut::<Events<Equip>>().clear();
+        world.resource_mut::<Events<Unequip>>().clear();
+    }
+
+    fn put_on_item(world: &mut World, item: Entity) {
+        world.send_event(Equip(item));
+        run(world);
+    }
+
+    fn take_off_item(world: &mut World, item: Entity) {
+        world.send_event(Unequip(item));
+        run(world);
+    }
+
+    fn power_and_defense(world: &World, player: Entity) -> (i32, i32) {
+        let stats = world.get::<CombatStats>(player).unwrap();
+        (stats.power, stats.defense)
+    }
+
+    #[test]
+    fn taking_everything_off_restores_the_stats() {
+        let (mut world, player, items) = carrying(&["Longsword", "Chain Mail", "Shield"]);
+        for &item in items.iter() {
+            put_on_item(&mut world, item);
+        }
+        assert_eq!(power_and_defense(&world, player), (8, 6));
+        assert!(items
+            .iter()
+            .all(|&item| world.get::<Equipped>(item).is_some()));
+
+        for &item in items.iter().rev() {
+            take_off_item(&mut world, item);
+        }
+        let stats = world.get::<CombatStats>(player).unwrap();
+        assert_eq!(
+            (stats.max_hp, stats.hp, stats.power, stats.defense),
+            (STATS.max_hp, STATS.hp, STATS.power, STATS.defense)
+        );
+        assert!(items
+            .iter()
+            .all(|&item| world.get::<Equipped>(item).is_none()));
+        assert_eq!(world.get::<Energy>(player).unwrap().0, -6 * MOVE_COST);
+    }
+
+    #[test]
+    fn putting_on_swaps_out_whatever_is_in_the_slot() {
+        let (mut world, player, items) = carrying(&["Dagger", "Longsword"]);
+        put_on_item(&mut world, items[0]);
+        put_on_item(&mut world, items[1]);
+        assert_eq!(power_and_defense(&world, player), (8, 2));
+        assert!(world.get::<Equipped>(items[0]).is_none());
+
+        // Wearing it again changes nothing.
+        put_on_item(&mut world, items[1]);
+        assert_eq!(power_and_defense(&world, player), (8, 2));
+    }
+
+    #[test]
+    fn a_light_sets_how_far_the_player_sees() {
+        let (mut world, player, items) = carrying(&["Torch"]);
+        put_on_item(&mut world, items[0]);
+        assert_eq!(world.get::<Viewshed>(player).unwrap().range, 8);
+        take_off_item(&mut world, items[0]);
+        assert_eq!(world.get::<Viewshed>(player).unwrap().range, PLAYER_SIGHT);
+        assert_eq!(power_and_defense(&world, player), (5, 2));
+    }
+}
@@ -1,4 +1,98 @@
+use crate::consumables::ItemEffect;
 use bevy::prelude::*;
 
-#[derive(Event)]
-pub struct Tick;
+/// Asks the player to step one tile. Sent by the keyboard, or by the autopilot when headless.
+#[derive(Event, Clone, Copy)]
+pub struct PlayerMove {
+    pub dx: i32,
+    pub dy: i32,
+}
+
+/// Picks up whatever is lying under the player.
+#[derive(Event, Clone, Copy)]
+pub struct PickUp;
+
+/// Drops an item from the player's pack onto their tile.
+#[derive(Event, Clone, Copy)]
+pub struct DropItem(pub Entity);
+
+/// Puts on an item from the player's pack, taking off whatever was in its slot.
+#[derive(Event, Clone, Copy)]
+pub struct Equip(pub Entity);
+
+/// Takes off an item the player is wearing. It stays in the pack.
+#[derive(Event, Clone, Copy)]
+pub struct Unequip(pub Entity);
+
+/// Uses up an item from the player's pack. Aimed items say which tile they were aimed at.
+#[derive(Event, Clone, Copy)]
+pub struct UseItem {
+    pub item: Entity,
+    pub target: Option<(usize, usize)>,
+}
+
+/// One effect of a used item landing on `target`.
+#[derive(Event, Clone, Copy)]
+pub struct ApplyItemEffect {
+    pub user: Entity,
+    pub target: Entity,
+    pub effect: ItemEffect,
+}
+
+/// `attacker` goes for `target`. Melee, arrows and spells are all settled the same way.
+#[derive(Event, Clone, Copy)]
+pub struct Attack {
+    pub attacker: Entity,
+    pub target: Entity,
+    pub kind: AttackKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttackKind {
+    Melee,
+    Arrow,
+    Spell,
+}
+
+/// `target` loses `amount` hp. Sent once an attack has been settled.
+#[derive(Event, Clone, Copy)]
+pub struct Damage {
+    pub source: Entity,
+    pub target: Entity,
+    pub amount: i32,
+}
+
+/// `target` earned `amount` xp, usually by killing something.
+#[derive(Event, Clone, Copy)]
+pub struct GainXp {
+    pub target: Entity,
+    pub amount: i32,
+}
+
+/// Spends one of the player's level-ups. Sent by the keyboard, or by the autopilot when
+/// headless.
+#[derive(Event, Clone, Copy)]
+pub struct ChooseImprovement(pub Improvement);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Improvement {
+    Power,
+    Defense,
+    Speed,
+}
+
+/// Something shook a monster's nerve: it got hurt, saw an ally die, or met someone much
+/// stronger than itself.
+#[derive(Event, Clone, Copy)]
+pub struct MoraleShock {
+    pub target: Entity,
+    pub amount: i32,
+}
+
+/// Something loud happened. Sleeping monsters within `radius` may wake up.
+#[derive(Event, Clone, Copy)]
+pub struct Noise {
+    pub x: usize,
+    pub y: usize,
+    pub radius: i32,
+}
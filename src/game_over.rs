@@ -0,0 +1,127 @@
+use crate::components::*;
+use crate::dijkstra::FlowMaps;
+use crate::gamelog::{GameLog, LogScroll};
+use crate::glyphs::GlyphFont;
+use crate::random::GameRng;
+use crate::resources::*;
+use crate::turn::*;
+use bevy::prelude::*;
+
+/// Clears the dead run away when a new one starts.
+pub struct GameOverPlugin;
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnExit(GameState::GameOver), clear_run);
+    }
+}
+
+/// Shows how the run ended and waits for Enter to start another. Only added when the game
+/// has a window.
+pub struct GameOverScreenPlugin;
+
+impl Plugin for GameOverScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(GameState::GameOver), show_game_over)
+            .add_systems(
+                Update,
+                restart_on_enter.run_if(in_state(GameState::GameOver)),
+            )
+            .add_systems(OnExit(GameState::GameOver), hide_game_over);
+    }
+}
+
+#[derive(Component)]
+struct GameOverScreen;
+
+/// Despawns everything the last dungeon was made of and resets what was kept about it, so
+/// entering `Playing` can build the next one from scratch.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn clear_run(
+    mut commands: Commands,
+    query_run: Query<
+        Entity,
+        Or<(
+            With<Tile>,
+            With<Room>,
+            With<Monster>,
+            With<Player>,
+            With<Item>,
+        )>,
+    >,
+    mut map: ResMut<Map>,
+    mut turn_count: ResMut<TurnCount>,
+    mut turn_order: ResMut<TurnOrder>,
+    mut flow_maps: ResMut<FlowMaps>,
+    mut death: ResMut<Death>,
+    mut log: ResMut<GameLog>,
+    // Only there when the game has a window.
+    scroll: Option<ResMut<LogScroll>>,
+    mut rng: ResMut<GameRng>,
+) {
+    for ent in query_run.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+    *map = Map::default();
+    *turn_count = TurnCount::default();
+    *turn_order = TurnOrder::default();
+    *flow_maps = FlowMaps::default();
+    *death = Death::default();
+    *log = GameLog::default();
+    if let Some(mut scroll) = scroll {
+        *scroll = LogScroll::default();
+    }
+    // A fresh seed for every run, so the one on the game-over screen replays it.
+    *rng = GameRng::default();
+}
+
+fn show_game_over(
+    mut commands: Commands,
+    glyph_font: Res<GlyphFont>,
+    death: Res<Death>,
+    rng: Res<GameRng>,
+) {
+    let size = Vec2::new(MAP_WIDTH as f32 * TILE_SIZE, MAP_HEIGHT as f32 * TILE_SIZE);
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::BLACK,
+                custom_size: Some(size),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, 9.0),
+            ..default()
+        },
+        GameOverScreen,
+    ));
+
+    let style = TextStyle {
+        font: glyph_font.font.clone(),
+        font_size: glyph_font.font_size * 2.0,
+        color: Color::WHITE,
+    };
+    let lines = format!(
+        "You died.\n{}.\nYou survived {} turns.\nSeed: {}\n\nPress Enter to play again.",
+        death.cause, death.turns, rng.seed
+    );
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_section(lines, style).with_justify(JustifyText::Center),
+            transform: Transform::from_xyz(0.0, 0.0, 10.0),
+            ..default()
+        },
+        GameOverScreen,
+    ));
+}
+
+fn restart_on_enter(keys: Res<ButtonInput<KeyCode>>, mut next_state: ResMut<NextState<GameState>>) {
+    if keys.just_pressed(KeyCode::Enter) {
+        next_state.set(GameState::Playing);
+    }
+}
+
+fn hide_game_over(mut commands: Commands, query_screen: Query<Entity, With<GameOverScreen>>) {
+    for ent in query_screen.iter() {
+        commands.entity(ent).despawn();
+    }
+}
@@ -0,0 +1,208 @@
+use crate::glyphs::GlyphFont;
+use crate::resources::*;
+use bevy::prelude::*;
+use std::io::Write;
+
+/// Where F2 writes the log.
+pub const LOG_EXPORT_PATH: &str = "game_log.txt";
+
+/// Everything that has happened this run, oldest first.
+#[derive(Resource, Default)]
+pub struct GameLog {
+    pub entries: Vec<LogEntry>,
+}
+
+pub struct LogEntry {
+    pub turn: u32,
+    pub text: String,
+    pub color: Color,
+    /// How many times in a row this was said on `turn`.
+    pub count: u32,
+}
+
+impl GameLog {
+    /// Adds a message, or bumps the count on the last one if it said the same thing on the
+    /// same turn.
+    pub fn add(&mut self, turn: u32, text: impl Into<String>, color: Color) {
+        let text = text.into();
+        if let Some(last) = self.entries.last_mut() {
+            if last.turn == turn && last.text == text {
+                last.count += 1;
+                return;
+            }
+        }
+        self.entries.push(LogEntry {
+            turn,
+            text,
+            color,
+            count: 1,
+        });
+    }
+
+    pub fn export(&self, path: &str) -> std::io::Result<()> {
+        let mut file = std::fs::File::create(path)?;
+        for entry in self.entries.iter() {
+            writeln!(file, "{entry}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "[{}] {}", self.turn, self.text)?;
+        if self.count > 1 {
+            write!(f, " x{}", self.count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shows the log in a panel under the map. PageUp and PageDown scroll back through it, F2
+/// saves it to `LOG_EXPORT_PATH`. Only added when the game has a window.
+pub struct GameLogPanelPlugin;
+
+impl Plugin for GameLogPanelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LogScroll>()
+            .add_systems(Startup, spawn_log_panel)
+            .add_systems(Update, (scroll_log, export_log, draw_log).chain());
+    }
+}
+
+/// How many entries back from the newest the panel is showing.
+#[derive(Resource, Default)]
+pub struct LogScroll(usize);
+
+#[derive(Component)]
+struct LogText;
+
+fn spawn_log_panel(mut commands: Commands) {
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(0.0),
+                width: Val::Percent(100.0),
+                height: Val::Px(LOG_PANEL_HEIGHT),
+                padding: UiRect::horizontal(Val::Px(TILE_SIZE)),
+                ..default()
+            },
+            background_color: Color::BLACK.into(),
+            ..default()
+        })
+        .with_children(|panel| {
+            panel.spawn((TextBundle::default(), LogText));
+        });
+}
+
+fn scroll_log(keys: Res<ButtonInput<KeyCode>>, log: Res<GameLog>, mut scroll: ResMut<LogScroll>) {
+    let max_scroll = log.entries.len().saturating_sub(LOG_LINES);
+    if keys.just_pressed(KeyCode::PageUp) {
+        scroll.0 = (scroll.0 + LOG_LINES).min(max_scroll);
+    } else if keys.just_pressed(KeyCode::PageDown) {
+        scroll.0 = scroll.0.saturating_sub(LOG_LINES);
+    }
+}
+
+fn export_log(
+    keys: Res<ButtonInput<KeyCode>>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+) {
+    if !keys.just_pressed(KeyCode::F2) {
+        return;
+    }
+    let message = match log.export(LOG_EXPORT_PATH) {
+        Ok(()) => format!("Saved the log to {LOG_EXPORT_PATH}"),
+        Err(err) => format!("Couldn't save the log: {err}"),
+    };
+    log.add(turn_count.0, message, Color::srgb(0.5, 0.5, 0.5));
+}
+
+fn draw_log(
+    log: Res<GameLog>,
+    scroll: Res<LogScroll>,
+    glyph_font: Res<GlyphFont>,
+    mut query_text: Query<&mut Text, With<LogText>>,
+) {
+    if !log.is_changed() && !scroll.is_changed() {
+        return;
+    }
+    let Ok(mut text) = query_text.get_single_mut() else {
+        return;
+    };
+    let end = log.entries.len().saturating_sub(scroll.0);
+    let start = end.saturating_sub(LOG_LINES);
+    text.sections = log.entries[start..end]
+        .iter()
+        .map(|entry| {
+            TextSection::new(
+                format!("{entry}\n"),
+                TextStyle {
+                    font: glyph_font.font.clone(),
+                    font_size: glyph_font.font_size,
+                    color: entry.color,
+                },
+            )
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(log: &GameLog) -> Vec<String> {
+        log.entries.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn repeats_on_one_turn_are_counted() {
+        let mut log = GameLog::default();
+        log.add(3, "The Goblin hits you for 1", Color::WHITE);
+        log.add(3, "The Goblin hits you for 1", Color::WHITE);
+        log.add(3, "You hit the Goblin for 4", Color::WHITE);
+        log.add(3, "The Goblin hits you for 1", Color::WHITE);
+        assert_eq!(
+            lines(&log),
+            [
+                "[3] The Goblin hits you for 1 x2",
+                "[3] You hit the Goblin for 4",
+                "[3] The Goblin hits you for 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn repeats_on_later_turns_keep_their_own_turn() {
+        let mut log = GameLog::default();
+        log.add(4, "The Goblin hits you for 1", Color::WHITE);
+        log.add(5, "The Goblin hits you for 1", Color::WHITE);
+        log.add(5, "The Goblin hits you for 1", Color::WHITE);
+        assert_eq!(
+            lines(&log),
+            [
+                "[4] The Goblin hits you for 1",
+                "[5] The Goblin hits you for 1 x2",
+            ]
+        );
+    }
+
+    #[test]
+    fn export_writes_a_line_per_entry() {
+        let mut log = GameLog::default();
+        log.add(1, "You see a Goblin", Color::WHITE);
+        log.add(2, "You hit the Goblin for 4", Color::WHITE);
+        log.add(2, "You hit the Goblin for 4", Color::WHITE);
+        let path = std::env::temp_dir().join(format!("game_log_{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        log.export(path).unwrap();
+        let written = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(
+            written,
+            "[1] You see a Goblin\n[2] You hit the Goblin for 4 x2\n"
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use crate::components::*;
+use crate::resources::*;
+use bevy::prelude::*;
+
+/// Draws every `Renderable` as a text glyph. Only added when the game has a window.
+pub struct GlyphPlugin;
+
+impl Plugin for GlyphPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GlyphFont>()
+            .add_systems(PreStartup, spawn_camera)
+            .add_systems(PreUpdate, add_glyphs);
+    }
+}
+
+#[derive(Resource)]
+pub struct GlyphFont {
+    pub font: Handle<Font>,
+    pub font_size: f32,
+}
+
+impl FromWorld for GlyphFont {
+    fn from_world(world: &mut World) -> Self {
+        GlyphFont {
+            font: world.load_asset("fonts/Mx437_IBM_BIOS.ttf"),
+            font_size: TILE_SIZE,
+        }
+    }
+}
+
+/// Looks a little below the middle of the map, so the map sits above the HUD and log.
+fn spawn_camera(mut commands: Commands) {
+    let mut camera = Camera2dBundle::default();
+    camera.transform.translation.y = -UI_HEIGHT / 2.0;
+    commands.spawn(camera);
+}
+
+fn add_glyphs(
+    mut commands: Commands,
+    glyph_font: Res<GlyphFont>,
+    query_renderables: Query<
+        (Entity, &Renderable, &Transform, Option<&Visibility>),
+        Added<Renderable>,
+    >,
+) {
+    for (ent, renderable, transform, visibility) in query_renderables.iter() {
+        commands.entity(ent).insert(Text2dBundle {
+            text: Text::from_section(
+                renderable.glyph,
+                TextStyle {
+                    font: glyph_font.font.clone(),
+                    font_size: glyph_font.font_size,
+                    color: renderable.color,
+                },
+            )
+            .with_justify(JustifyText::Center),
+            transform: *transform,
+            visibility: visibility.copied().unwrap_or_default(),
+            ..default()
+        });
+    }
+}
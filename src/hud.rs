@@ -0,0 +1,170 @@
+use crate::components::*;
+use crate::effects::{Effects, EffectsItem};
+use crate::glyphs::GlyphFont;
+use crate::progression::xp_to_next_level;
+use crate::resources::*;
+use bevy::prelude::*;
+
+const HP_BAR_WIDTH: f32 = TILE_SIZE * 20.0;
+
+/// HP, depth, turn count and how the player is doing, in a strip between the map and the
+/// log. Only added when the game has a window.
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        // Drawn after Update so effects that wore off this frame are already gone.
+        app.add_systems(Startup, spawn_hud)
+            .add_systems(PostUpdate, update_hud);
+    }
+}
+
+#[derive(Component)]
+struct HpText;
+
+#[derive(Component)]
+struct HpBar;
+
+#[derive(Component)]
+struct StatusText;
+
+fn spawn_hud(mut commands: Commands, glyph_font: Res<GlyphFont>) {
+    let style = TextStyle {
+        font: glyph_font.font.clone(),
+        font_size: glyph_font.font_size,
+        color: Color::WHITE,
+    };
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(LOG_PANEL_HEIGHT),
+                width: Val::Percent(100.0),
+                height: Val::Px(HUD_HEIGHT),
+                padding: UiRect::horizontal(Val::Px(TILE_SIZE)),
+                column_gap: Val::Px(TILE_SIZE),
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: Color::BLACK.into(),
+            ..default()
+        })
+        .with_children(|hud| {
+            hud.spawn((TextBundle::from_section("", style.clone()), HpText));
+            hud.spawn(NodeBundle {
+                style: Style {
+                    width: Val::Px(HP_BAR_WIDTH),
+                    height: Val::Px(TILE_SIZE),
+                    ..default()
+                },
+                background_color: Color::srgb(0.3, 0.0, 0.0).into(),
+                ..default()
+            })
+            .with_children(|bar| {
+                bar.spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Percent(100.0),
+                            height: Val::Percent(100.0),
+                            ..default()
+                        },
+                        background_color: Color::srgb(0.9, 0.0, 0.0).into(),
+                        ..default()
+                    },
+                    HpBar,
+                ));
+            });
+            hud.spawn((TextBundle::from_section("", style), StatusText));
+        });
+}
+
+/// Only redraws when something it shows has changed.
+#[allow(clippy::type_complexity)]
+fn update_hud(
+    query_player: Query<(Ref<CombatStats>, Ref<Hunger>, Ref<Experience>, Effects), With<Player>>,
+    turn_count: Res<TurnCount>,
+    map: Res<Map>,
+    mut query_hp_text: Query<&mut Text, (With<HpText>, Without<StatusText>)>,
+    mut query_hp_bar: Query<&mut Style, With<HpBar>>,
+    mut query_status: Query<&mut Text, (With<StatusText>, Without<HpText>)>,
+) {
+    let Ok((stats, hunger, experience, effects)) = query_player.get_single() else {
+        return;
+    };
+    if !stats.is_changed()
+        && !hunger.is_changed()
+        && !experience.is_changed()
+        && !effects.is_changed()
+        && !turn_count.is_changed()
+        && !map.is_changed()
+    {
+        return;
+    }
+
+    if let Ok(mut text) = query_hp_text.get_single_mut() {
+        text.sections[0].value = format!("HP: {} / {}", stats.hp, stats.max_hp);
+    }
+    if let Ok(mut style) = query_hp_bar.get_single_mut() {
+        let filled = stats.hp.max(0) as f32 / stats.max_hp.max(1) as f32;
+        style.width = Val::Percent(filled * 100.0);
+    }
+    if let Ok(mut text) = query_status.get_single_mut() {
+        let style = text.sections[0].style.clone();
+        text.sections = vec![TextSection::new(
+            format!(
+                "Depth: {}   Level: {}   XP: {}/{}   Power: {}   Defense: {}   Turn: {}   ",
+                map.depth,
+                experience.level,
+                experience.xp,
+                xp_to_next_level(experience.level),
+                stats.power,
+                stats.defense,
+                turn_count.0
+            ),
+            style.clone(),
+        )];
+        text.sections
+            .extend(conditions(&hunger, &experience, &effects).into_iter().map(
+                |(label, color)| {
+                    TextSection::new(
+                        format!("{label}  "),
+                        TextStyle {
+                            color,
+                            ..style.clone()
+                        },
+                    )
+                },
+            ));
+    }
+}
+
+/// Everything worth pointing out about the player's state, with the colour to show it in.
+fn conditions(
+    hunger: &Hunger,
+    experience: &Experience,
+    effects: &EffectsItem,
+) -> Vec<(String, Color)> {
+    let mut conditions = Vec::new();
+    if experience.unspent > 0 {
+        conditions.push(("Level up! (1-3)".to_string(), Color::srgb(1.0, 0.85, 0.0)));
+    }
+    match hunger.state {
+        HungerState::WellFed => {
+            conditions.push(("Well Fed".to_string(), Color::srgb(0.0, 1.0, 0.0)));
+        }
+        HungerState::Normal => {}
+        HungerState::Hungry => {
+            conditions.push(("Hungry".to_string(), Color::srgb(1.0, 0.6, 0.0)));
+        }
+        HungerState::Starving => {
+            conditions.push(("Starving".to_string(), Color::srgb(1.0, 0.0, 0.0)));
+        }
+    }
+    conditions.extend(
+        effects
+            .labels()
+            .into_iter()
+            .map(|(label, color)| (label.to_string(), color)),
+    );
+    conditions
+}
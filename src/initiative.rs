@@ -0,0 +1,159 @@
+use crate::components::*;
+use crate::effects::*;
+use crate::resources::*;
+use crate::turn::*;
+use bevy::prelude::*;
+use std::cmp::Reverse;
+
+pub const ACTION_THRESHOLD: i32 = 100;
+pub const MOVE_COST: i32 = 100;
+pub const NORMAL_SPEED: i32 = 10;
+
+pub struct InitiativePlugin;
+
+impl Plugin for InitiativePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TurnOrder>().add_systems(
+            Update,
+            schedule_monsters
+                .in_set(TurnSet::MonsterTurn)
+                .before(ActingSet),
+        );
+    }
+}
+
+/// Systems that let the monsters in `TurnOrder` act.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ActingSet;
+
+/// Hands out energy until somebody can act. Monsters that are ready go into `TurnOrder`,
+/// most energy first and ties broken by entity so a seeded run always plays out the same.
+/// If only the player is ready the queue is left empty and the monster turn ends.
+#[allow(clippy::type_complexity)]
+fn schedule_monsters(
+    mut query_actors: Query<(
+        Entity,
+        &Speed,
+        &mut Energy,
+        Has<Player>,
+        Has<Hasted>,
+        Has<Slowed>,
+    )>,
+    mut turn_order: ResMut<TurnOrder>,
+) {
+    turn_order.acting.clear();
+    loop {
+        let mut ready = Vec::new();
+        let mut player_ready = false;
+        for (ent, _speed, energy, is_player, ..) in query_actors.iter() {
+            if energy.0 < ACTION_THRESHOLD {
+                continue;
+            }
+            if is_player {
+                player_ready = true;
+            } else {
+                ready.push((energy.0, ent));
+            }
+        }
+
+        if !ready.is_empty() {
+            ready.sort_by_key(|&(energy, ent)| (Reverse(energy), ent));
+            turn_order.acting = ready.into_iter().map(|(_, ent)| ent).collect();
+            return;
+        }
+        if player_ready {
+            return;
+        }
+
+        for (_ent, speed, mut energy, _is_player, hasted, slowed) in query_actors.iter_mut() {
+            energy.0 += effective_speed(speed.0, hasted, slowed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::schedule::Schedule;
+
+    fn actor(world: &mut World, speed: i32) -> Entity {
+        world.spawn((Speed(speed), Energy(0))).id()
+    }
+
+    /// Who acted, in order, over `player_turns` turns of the player. Every monster that gets
+    /// into `TurnOrder` spends a move, like it would in the game.
+    fn play(world: &mut World, player: Entity, player_turns: usize) -> Vec<Entity> {
+        let mut schedule = Schedule::default();
+        schedule.add_systems(schedule_monsters);
+        let mut acted = Vec::new();
+        for _ in 0..player_turns {
+            loop {
+                schedule.run(world);
+                let acting = std::mem::take(&mut world.resource_mut::<TurnOrder>().acting);
+                if acting.is_empty() {
+                    break;
+                }
+                for monster in acting {
+                    world.get_mut::<Energy>(monster).unwrap().0 -= MOVE_COST;
+                    acted.push(monster);
+                }
+            }
+            world.get_mut::<Energy>(player).unwrap().0 -= MOVE_COST;
+            acted.push(player);
+        }
+        acted
+    }
+
+    fn world_with_player() -> (World, Entity) {
+        let mut world = World::new();
+        world.init_resource::<TurnOrder>();
+        let player = world.spawn((Player, Speed(NORMAL_SPEED), Energy(0))).id();
+        (world, player)
+    }
+
+    #[test]
+    fn actions_follow_speed() {
+        let (mut world, player) = world_with_player();
+        let goblin = actor(&mut world, 20);
+        let ogre = actor(&mut world, 5);
+        let acted = play(&mut world, player, 10);
+        let count = |who| acted.iter().filter(|&&ent| ent == who).count();
+        assert_eq!(count(player), 10);
+        assert_eq!(count(goblin), 20);
+        assert_eq!(count(ogre), 5);
+
+        // Two goblin moves before every player move, and an ogre move every other one.
+        let between: Vec<Vec<Entity>> = acted
+            .split(|&ent| ent == player)
+            .take(10)
+            .map(<[Entity]>::to_vec)
+            .collect();
+        for (turn, monsters) in between.iter().enumerate() {
+            assert_eq!(monsters.iter().filter(|&&ent| ent == goblin).count(), 2);
+            let ogre_moves = monsters.iter().filter(|&&ent| ent == ogre).count();
+            assert_eq!(ogre_moves, turn % 2, "turn {turn}");
+        }
+    }
+
+    #[test]
+    fn acting_order_is_the_same_every_time() {
+        let order = || {
+            let (mut world, player) = world_with_player();
+            let first = actor(&mut world, NORMAL_SPEED);
+            let second = actor(&mut world, NORMAL_SPEED);
+            let fast = actor(&mut world, 15);
+            let acted = play(&mut world, player, 8);
+            (acted, [first, second, fast])
+        };
+        let (acted, [first, second, _]) = order();
+        assert_eq!(acted, order().0);
+
+        // Equally fast monsters go in entity order.
+        let ties: Vec<Entity> = acted
+            .iter()
+            .copied()
+            .filter(|&ent| ent == first || ent == second)
+            .collect();
+        assert!(ties.chunks(2).all(|pair| pair == [first, second]));
+    }
+}
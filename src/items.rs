@@ -0,0 +1,510 @@
+use crate::components::*;
+use crate::effects::Asleep;
+use crate::events::*;
+use crate::gamelog::GameLog;
+use crate::glyphs::GlyphFont;
+use crate::initiative::MOVE_COST;
+use crate::map::get_tile_idx;
+use crate::monsters::add_monsters;
+use crate::player::PlayerActionSet;
+use crate::random::GameRng;
+use crate::raws::*;
+use crate::resources::*;
+use crate::spawn_table::*;
+use crate::targeting::{TargetAction, Targeting};
+use crate::turn::*;
+use bevy::prelude::*;
+
+/// Between the floor and anyone standing on it.
+const ITEM_Z: f32 = 1.5;
+
+/// Puts items in the dungeon and lets the player pick them up and drop them again.
+pub struct ItemPlugin;
+
+impl Plugin for ItemPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ItemRaws>()
+            .add_event::<PickUp>()
+            .add_event::<DropItem>()
+            // Rolled after the monsters so a seed always gives the same dungeon.
+            .add_systems(OnEnter(GameState::Playing), add_items.after(add_monsters))
+            .add_systems(
+                Update,
+                (pick_up, drop_item)
+                    .in_set(TurnSet::AwaitingInput)
+                    .in_set(PlayerActionSet),
+            );
+    }
+}
+
+/// D, U or E opens a list of what the player is carrying, and a letter picks the item to drop,
+/// use, or put on and take off. Items that have to be aimed go on to targeting. Escape closes
+/// it. Only added when the game has a window.
+pub struct ItemMenuPlugin;
+
+impl Plugin for ItemMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                open_item_menu
+                    .in_set(TurnSet::AwaitingInput)
+                    .before(PlayerActionSet),
+                choose_item.in_set(TurnSet::ItemMenu),
+            ),
+        )
+        .add_systems(OnEnter(TurnState::ItemMenu), show_item_menu)
+        .add_systems(OnExit(TurnState::ItemMenu), hide_item_menu);
+    }
+}
+
+/// Only exists while the item menu is open.
+#[derive(Resource)]
+pub struct ItemMenu {
+    pub action: ItemAction,
+}
+
+/// What happens to the item picked from the menu.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ItemAction {
+    Drop,
+    Use,
+    Equip,
+}
+
+#[derive(Component)]
+struct ItemMenuScreen;
+
+const LETTERS: [KeyCode; 26] = [
+    KeyCode::KeyA,
+    KeyCode::KeyB,
+    KeyCode::KeyC,
+    KeyCode::KeyD,
+    KeyCode::KeyE,
+    KeyCode::KeyF,
+    KeyCode::KeyG,
+    KeyCode::KeyH,
+    KeyCode::KeyI,
+    KeyCode::KeyJ,
+    KeyCode::KeyK,
+    KeyCode::KeyL,
+    KeyCode::KeyM,
+    KeyCode::KeyN,
+    KeyCode::KeyO,
+    KeyCode::KeyP,
+    KeyCode::KeyQ,
+    KeyCode::KeyR,
+    KeyCode::KeyS,
+    KeyCode::KeyT,
+    KeyCode::KeyU,
+    KeyCode::KeyV,
+    KeyCode::KeyW,
+    KeyCode::KeyX,
+    KeyCode::KeyY,
+    KeyCode::KeyZ,
+];
+
+fn add_items(
+    mut commands: Commands,
+    query_rooms: Query<&Room>,
+    query_transform: Query<&Transform>,
+    map: Res<Map>,
+    raws: Res<ItemRaws>,
+    spawn_table: Res<SpawnTable>,
+    mut rng: ResMut<GameRng>,
+) {
+    for room in map.rooms.iter() {
+        let room = query_rooms.get(*room).unwrap();
+        for (template, tile) in
+            roll_room_items(&spawn_table, &raws, map.depth, &room.rect, &mut rng)
+        {
+            spawn_item(&mut commands, template, tile, &map, &query_transform);
+        }
+    }
+}
+
+pub fn spawn_item(
+    commands: &mut Commands,
+    template: &ItemTemplate,
+    (x, y): (usize, usize),
+    map: &Map,
+    query_transform: &Query<&Transform>,
+) {
+    let tile_trans = query_transform.get(map.tiles[get_tile_idx(x, y)]).unwrap();
+    let mut item = commands.spawn((
+        Renderable {
+            glyph: template.glyph,
+            color: template.color(),
+        },
+        Transform::from_xyz(tile_trans.translation.x, tile_trans.translation.y, ITEM_Z),
+        Visibility::Hidden,
+        Position { x, y },
+        Item,
+        Name::new(template.name.clone()),
+    ));
+    if let Some(consumable) = &template.consumable {
+        item.insert(consumable.clone());
+    }
+    if let Some(equipment) = template.equipment {
+        item.insert(equipment);
+    }
+}
+
+/// Takes the turn if something was picked up.
+#[allow(clippy::too_many_arguments)]
+fn pick_up(
+    mut commands: Commands,
+    mut events: EventReader<PickUp>,
+    mut query_player: Query<(&Position, &mut Inventory, &mut Energy, Has<Asleep>), With<Player>>,
+    query_items: Query<(Entity, &Position, &Name), With<Item>>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+    mut next_state: ResMut<NextState<TurnState>>,
+) {
+    if events.read().last().is_none() {
+        return;
+    }
+    let (player_pos, mut inventory, mut energy, asleep) = query_player.single_mut();
+    if asleep {
+        return;
+    }
+    let Some((item, _, name)) = query_items
+        .iter()
+        .find(|(_, position, _)| position.x == player_pos.x && position.y == player_pos.y)
+    else {
+        log.add(
+            turn_count.0,
+            "There is nothing here to pick up",
+            Color::srgb(0.5, 0.5, 0.5),
+        );
+        return;
+    };
+    if inventory.items.len() >= inventory.capacity {
+        log.add(
+            turn_count.0,
+            "Your pack is full",
+            Color::srgb(0.5, 0.5, 0.5),
+        );
+        return;
+    }
+
+    inventory.items.push(item);
+    commands
+        .entity(item)
+        .remove::<(Position, Remembered)>()
+        .insert(Visibility::Hidden);
+    log.add(
+        turn_count.0,
+        format!("You pick up the {name}"),
+        Color::WHITE,
+    );
+    energy.0 -= MOVE_COST;
+    next_state.set(TurnState::PlayerTurn);
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn drop_item(
+    mut commands: Commands,
+    mut events: EventReader<DropItem>,
+    mut query_player: Query<
+        (
+            &Position,
+            &Transform,
+            &mut Inventory,
+            &mut Energy,
+            Has<Asleep>,
+        ),
+        With<Player>,
+    >,
+    query_names: Query<(&Name, Has<Equipped>), With<Item>>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+    mut next_state: ResMut<NextState<TurnState>>,
+) {
+    let Some(DropItem(item)) = events.read().last().copied() else {
+        return;
+    };
+    let (player_pos, player_transform, mut inventory, mut energy, asleep) =
+        query_player.single_mut();
+    if asleep {
+        return;
+    }
+    let Some(idx) = inventory.items.iter().position(|&carried| carried == item) else {
+        return;
+    };
+    let Ok((name, worn)) = query_names.get(item) else {
+        return;
+    };
+    if worn {
+        log.add(
+            turn_count.0,
+            format!("You have to take off the {name} first"),
+            Color::srgb(0.5, 0.5, 0.5),
+        );
+        return;
+    }
+
+    inventory.items.remove(idx);
+    commands.entity(item).insert((
+        Position {
+            x: player_pos.x,
+            y: player_pos.y,
+        },
+        Transform::from_xyz(
+            player_transform.translation.x,
+            player_transform.translation.y,
+            ITEM_Z,
+        ),
+    ));
+    log.add(turn_count.0, format!("You drop the {name}"), Color::WHITE);
+    energy.0 -= MOVE_COST;
+    next_state.set(TurnState::PlayerTurn);
+}
+
+fn open_item_menu(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    query_player: Query<&Inventory, With<Player>>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+    mut next_state: ResMut<NextState<TurnState>>,
+) {
+    let action = if keys.just_pressed(KeyCode::KeyD) {
+        ItemAction::Drop
+    } else if keys.just_pressed(KeyCode::KeyU) {
+        ItemAction::Use
+    } else if keys.just_pressed(KeyCode::KeyE) {
+        ItemAction::Equip
+    } else {
+        return;
+    };
+    let Ok(inventory) = query_player.get_single() else {
+        return;
+    };
+    if inventory.items.is_empty() {
+        log.add(
+            turn_count.0,
+            "You aren't carrying anything",
+            Color::srgb(0.5, 0.5, 0.5),
+        );
+        return;
+    }
+    commands.insert_resource(ItemMenu { action });
+    next_state.set(TurnState::ItemMenu);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn choose_item(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    menu: Res<ItemMenu>,
+    query_player: Query<(&Position, &Viewshed, &Inventory), With<Player>>,
+    query_consumables: Query<&Consumable, With<Item>>,
+    query_worn: Query<(), (With<Item>, With<Equipped>)>,
+    query_monsters: Query<(&Monster, &Position)>,
+    mut drops: EventWriter<DropItem>,
+    mut uses: EventWriter<UseItem>,
+    mut equips: EventWriter<Equip>,
+    mut unequips: EventWriter<Unequip>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+    mut next_state: ResMut<NextState<TurnState>>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        next_state.set(TurnState::AwaitingInput);
+        return;
+    }
+    let (player_pos, viewshed, inventory) = query_player.single();
+    let Some(&item) = LETTERS
+        .iter()
+        .position(|&letter| keys.just_pressed(letter))
+        .and_then(|idx| inventory.items.get(idx))
+    else {
+        return;
+    };
+    match (menu.action, query_consumables.get(item)) {
+        (ItemAction::Drop, _) => {
+            drops.send(DropItem(item));
+        }
+        (ItemAction::Use, Ok(Consumable { aim: Some(aim), .. })) => {
+            commands.insert_resource(Targeting::at_nearest(
+                *aim,
+                TargetAction::UseItem(item),
+                (player_pos.x, player_pos.y),
+                viewshed,
+                &query_monsters,
+            ));
+            next_state.set(TurnState::Targeting);
+            return;
+        }
+        (ItemAction::Use, Ok(_)) => {
+            uses.send(UseItem { item, target: None });
+        }
+        (ItemAction::Use, Err(_)) => log.add(
+            turn_count.0,
+            "You can't use that",
+            Color::srgb(0.5, 0.5, 0.5),
+        ),
+        (ItemAction::Equip, _) => {
+            if query_worn.contains(item) {
+                unequips.send(Unequip(item));
+            } else {
+                equips.send(Equip(item));
+            }
+        }
+    };
+    // The item is dealt with once input is back, like any other action.
+    next_state.set(TurnState::AwaitingInput);
+}
+
+fn show_item_menu(
+    mut commands: Commands,
+    glyph_font: Res<GlyphFont>,
+    menu: Res<ItemMenu>,
+    query_player: Query<&Inventory, With<Player>>,
+    query_names: Query<(&Name, Has<Equipped>), With<Item>>,
+) {
+    let inventory = query_player.single();
+    let title = match menu.action {
+        ItemAction::Drop => "Drop which item?",
+        ItemAction::Use => "Use which item?",
+        ItemAction::Equip => "Put on or take off which item?",
+    };
+    let mut lines = format!("{title}\n\n");
+    for (item, letter) in inventory.items.iter().zip('a'..='z') {
+        if let Ok((name, worn)) = query_names.get(*item) {
+            let worn = if worn { " (worn)" } else { "" };
+            lines.push_str(&format!("{letter}) {name}{worn}\n"));
+        }
+    }
+    lines.push_str("\nEsc to cancel");
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            ItemMenuScreen,
+        ))
+        .with_children(|screen| {
+            screen
+                .spawn(NodeBundle {
+                    style: Style {
+                        padding: UiRect::all(Val::Px(TILE_SIZE)),
+                        border: UiRect::all(Val::Px(1.0)),
+                        ..default()
+                    },
+                    background_color: Color::BLACK.into(),
+                    border_color: Color::WHITE.into(),
+                    ..default()
+                })
+                .with_children(|panel| {
+                    panel.spawn(TextBundle::from_section(
+                        lines,
+                        TextStyle {
+                            font: glyph_font.font.clone(),
+                            font_size: glyph_font.font_size,
+                            color: Color::WHITE,
+                        },
+                    ));
+                });
+        });
+}
+
+fn hide_item_menu(mut commands: Commands, query_screen: Query<Entity, With<ItemMenuScreen>>) {
+    for ent in query_screen.iter() {
+        commands.entity(ent).despawn_recursive();
+    }
+    commands.remove_resource::<ItemMenu>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::schedule::Schedule;
+
+    /// A player at (3, 3) carrying two things in a pack that holds two, standing on a third.
+    fn full_pack() -> (World, Entity, Entity) {
+        let mut world = World::new();
+        world.init_resource::<Events<PickUp>>();
+        world.init_resource::<Events<DropItem>>();
+        world.init_resource::<TurnCount>();
+        world.init_resource::<GameLog>();
+        world.init_resource::<NextState<TurnState>>();
+        let carried = vec![
+            world.spawn((Item, Name::new("Dagger"))).id(),
+            world.spawn((Item, Name::new("Bread"))).id(),
+        ];
+        let underfoot = world
+            .spawn((Item, Name::new("Lantern"), Position { x: 3, y: 3 }))
+            .id();
+        let player = world
+            .spawn((
+                Player,
+                Position { x: 3, y: 3 },
+                Transform::default(),
+                Inventory {
+                    items: carried,
+                    capacity: 2,
+                },
+                Energy(0),
+            ))
+            .id();
+        (world, player, underfoot)
+    }
+
+    fn take_action(world: &mut World) {
+        let mut schedule = Schedule::default();
+        schedule.add_systems((pick_up, drop_item));
+        schedule.run(world);
+    }
+
+    fn last_message(world: &World) -> &str {
+        &world.resource::<GameLog>().entries.last().unwrap().text
+    }
+
+    #[test]
+    fn a_full_pack_leaves_the_item_where_it_lies() {
+        let (mut world, player, underfoot) = full_pack();
+        world.send_event(PickUp);
+        take_action(&mut world);
+
+        assert_eq!(last_message(&world), "Your pack is full");
+        assert_eq!(world.get::<Inventory>(player).unwrap().items.len(), 2);
+        assert!(world.get::<Position>(underfoot).is_some());
+        assert_eq!(world.get::<Energy>(player).unwrap().0, 0, "no turn taken");
+    }
+
+    #[test]
+    fn dropping_makes_room_to_pick_up() {
+        let (mut world, player, underfoot) = full_pack();
+        let dagger = world.get::<Inventory>(player).unwrap().items[0];
+        world.send_event(DropItem(dagger));
+        take_action(&mut world);
+
+        assert_eq!(last_message(&world), "You drop the Dagger");
+        assert_eq!(world.get::<Inventory>(player).unwrap().items.len(), 1);
+        let dropped = world.get::<Position>(dagger).unwrap();
+        assert_eq!((dropped.x, dropped.y), (3, 3));
+
+        // Both are underfoot now. Whichever comes first fills the pack again.
+        world.send_event(PickUp);
+        take_action(&mut world);
+        let items = &world.get::<Inventory>(player).unwrap().items;
+        assert_eq!(items.len(), 2);
+        let on_floor = [dagger, underfoot]
+            .into_iter()
+            .filter(|&item| world.get::<Position>(item).is_some())
+            .count();
+        assert_eq!(on_floor, 1);
+        assert_eq!(world.get::<Energy>(player).unwrap().0, -2 * MOVE_COST);
+    }
+}
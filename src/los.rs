@@ -0,0 +1,184 @@
+use crate::components::*;
+use crate::map::get_tile_idx;
+use crate::resources::Map;
+use bevy::ecs::query::QueryFilter;
+use bevy::prelude::*;
+
+/// Tiles walked from `from` towards `to`, not including `from`.
+/// If something is in the way the path stops on the blocking tile.
+pub struct LineOfSight {
+    pub path: Vec<(usize, usize)>,
+    pub blocked_at: Option<(usize, usize)>,
+}
+
+impl LineOfSight {
+    pub fn is_clear(&self) -> bool {
+        self.blocked_at.is_none()
+    }
+}
+
+/// Can `from` see `to`? Only walls block sight.
+pub fn line_of_sight<F: QueryFilter>(
+    map: &Map,
+    query_tiles: &Query<&Tile, F>,
+    from: (usize, usize),
+    to: (usize, usize),
+) -> LineOfSight {
+    cast_line(from, to, |(x, y)| {
+        query_tiles
+            .get(map.tiles[get_tile_idx(x, y)])
+            .is_ok_and(|tile| tile.tiletype == TileType::Wall)
+    })
+}
+
+/// Can something thrown from `from` reach `to`? Walls and occupied tiles block it.
+pub fn line_of_fire<F: QueryFilter>(
+    map: &Map,
+    query_tiles: &Query<&Tile, F>,
+    from: (usize, usize),
+    to: (usize, usize),
+) -> LineOfSight {
+    cast_line(from, to, |(x, y)| {
+        query_tiles
+            .get(map.tiles[get_tile_idx(x, y)])
+            .is_ok_and(|tile| tile.blocked)
+    })
+}
+
+/// Every tile within `range` of `origin` that `origin` can see, walls included.
+pub fn field_of_view<F: QueryFilter>(
+    map: &Map,
+    query_tiles: &Query<&Tile, F>,
+    origin: (usize, usize),
+    range: i32,
+) -> Vec<(usize, usize)> {
+    let (ox, oy) = (origin.0 as i32, origin.1 as i32);
+    let mut visible = Vec::new();
+    for y in (oy - range).max(0)..=(oy + range).min(map.tile_res.height as i32 - 1) {
+        for x in (ox - range).max(0)..=(ox + range).min(map.tile_res.width as i32 - 1) {
+            if (x - ox).pow(2) + (y - oy).pow(2) > range * range {
+                continue;
+            }
+            let tile = (x as usize, y as usize);
+            if line_of_sight(map, query_tiles, origin, tile).is_clear() {
+                visible.push(tile);
+            }
+        }
+    }
+    visible
+}
+
+/// Walks the line between `from` and `to` and stops at the first tile for which `blocks`
+/// is true. The endpoints themselves never block.
+pub fn cast_line(
+    from: (usize, usize),
+    to: (usize, usize),
+    blocks: impl Fn((usize, usize)) -> bool,
+) -> LineOfSight {
+    let mut path = Vec::new();
+    for tile in bresenham_line(from, to).into_iter().skip(1) {
+        path.push(tile);
+        if tile != to && blocks(tile) {
+            return LineOfSight {
+                path,
+                blocked_at: Some(tile),
+            };
+        }
+    }
+    LineOfSight {
+        path,
+        blocked_at: None,
+    }
+}
+
+/// Bresenham line from `from` to `to`, both included. The line is always drawn from the
+/// smaller endpoint, so A to B visits the same tiles as B to A.
+pub fn bresenham_line(from: (usize, usize), to: (usize, usize)) -> Vec<(usize, usize)> {
+    let (start, end, reversed) = if from <= to {
+        (from, to, false)
+    } else {
+        (to, from, true)
+    };
+
+    let (mut x, mut y) = (start.0 as i32, start.1 as i32);
+    let (x1, y1) = (end.0 as i32, end.1 as i32);
+    let dx = (x1 - x).abs();
+    let dy = -(y1 - y).abs();
+    let sx = if x < x1 { 1 } else { -1 };
+    let sy = if y < y1 { 1 } else { -1 };
+    let mut err = dx + dy;
+
+    let mut line = Vec::new();
+    loop {
+        line.push((x as usize, y as usize));
+        if x == x1 && y == y1 {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+
+    if reversed {
+        line.reverse();
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_is_the_same_both_ways() {
+        let pairs = [
+            ((0, 0), (7, 3)),
+            ((2, 9), (5, 1)),
+            ((4, 4), (4, 0)),
+            ((1, 6), (8, 6)),
+        ];
+        for (a, b) in pairs {
+            let mut back = bresenham_line(b, a);
+            back.reverse();
+            assert_eq!(bresenham_line(a, b), back, "{a:?} to {b:?}");
+        }
+    }
+
+    #[test]
+    fn line_includes_both_endpoints() {
+        let line = bresenham_line((3, 2), (9, 5));
+        assert_eq!(line.first(), Some(&(3, 2)));
+        assert_eq!(line.last(), Some(&(9, 5)));
+        assert_eq!(bresenham_line((4, 4), (4, 4)), vec![(4, 4)]);
+    }
+
+    #[test]
+    fn line_steps_one_tile_at_a_time() {
+        let line = bresenham_line((0, 0), (6, 11));
+        for pair in line.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            assert!(a.0.abs_diff(b.0) <= 1 && a.1.abs_diff(b.1) <= 1);
+        }
+    }
+
+    #[test]
+    fn cast_stops_at_first_blocking_tile() {
+        let los = cast_line((0, 0), (6, 0), |tile| tile == (3, 0) || tile == (4, 0));
+        assert_eq!(los.blocked_at, Some((3, 0)));
+        assert_eq!(los.path, vec![(1, 0), (2, 0), (3, 0)]);
+        assert!(!los.is_clear());
+    }
+
+    #[test]
+    fn cast_ignores_blocking_endpoints() {
+        let los = cast_line((0, 0), (4, 0), |tile| tile == (0, 0) || tile == (4, 0));
+        assert!(los.is_clear());
+        assert_eq!(los.path, vec![(1, 0), (2, 0), (3, 0), (4, 0)]);
+    }
+}
@@ -1,22 +1,120 @@
 use bevy::{prelude::*, window::WindowResolution};
+use std::str::FromStr;
+mod aoe;
+mod combat;
+use combat::*;
+mod consumables;
+use consumables::*;
 mod components;
+use components::*;
 mod player;
-use bevy_rapier2d::prelude::*;
 use monsters::MonsterPlugin;
 use player::*;
 mod resources;
 use resources::*;
 mod map;
 use map::*;
+mod debug;
+use debug::*;
+mod dijkstra;
+use dijkstra::*;
+mod game_over;
+use game_over::*;
+mod gamelog;
+use gamelog::*;
+mod glyphs;
+use glyphs::*;
+mod headless;
+mod hud;
+use headless::*;
+use hud::*;
+mod initiative;
+use initiative::*;
+mod items;
+use items::*;
+mod los;
+mod pathfinding;
+mod progression;
+use progression::*;
+mod random;
+use random::*;
+mod raws;
 mod rect;
+mod spawn_table;
+mod targeting;
+use targeting::*;
 mod visibility;
 use visibility::*;
+mod effects;
+use effects::*;
+mod equipment;
+use equipment::*;
 mod events;
-use events::*;
 mod monsters;
+mod turn;
+use turn::*;
+
+/// Everything the game needs to run, with or without a window.
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins((
+            TurnPlugin,
+            InitiativePlugin,
+            CombatPlugin,
+            GameOverPlugin,
+            StatusEffectsPlugin,
+            DijkstraPlugin,
+            PlayerPlugin,
+            MapPlugin,
+            MonsterPlugin,
+            VisibilityPlugin,
+            ProgressionPlugin,
+            ItemPlugin,
+            ConsumablePlugin,
+            EquipmentPlugin,
+        ))
+        .init_resource::<GameRng>()
+        .init_resource::<GameLog>()
+        .init_resource::<Map>();
+    }
+}
 
 fn main() {
-    let window_resolution = WindowResolution::new(800.0, 600.0);
+    let args: Vec<String> = std::env::args().collect();
+    let seed = arg_value(&args, "--seed").unwrap_or_else(rand::random);
+
+    // `--headless <turns>` plays that many turns without a window and prints where things ended up.
+    if let Some(turns) = arg_value(&args, "--headless") {
+        let mut app = headless_app(seed);
+        run_turns(&mut app, turns);
+        // `--log <path>` saves the message log once the turns are done.
+        if let Some(path) = arg_value::<String>(&args, "--log") {
+            if let Err(err) = app.world().resource::<GameLog>().export(&path) {
+                eprintln!("couldn't save the log to {path}: {err}");
+            }
+        }
+        let world = app.world_mut();
+        if *world.resource::<State<GameState>>() == GameState::GameOver {
+            let death = world.resource::<Death>();
+            println!("seed {seed}: {} after {} turns", death.cause, death.turns);
+            return;
+        }
+        let player_pos = world
+            .query_filtered::<&Position, With<Player>>()
+            .single(world);
+        println!(
+            "seed {seed}: {turns} turns, player at ({}, {})",
+            player_pos.x, player_pos.y
+        );
+        return;
+    }
+
+    let window_resolution = WindowResolution::new(
+        MAP_WIDTH as f32 * TILE_SIZE,
+        MAP_HEIGHT as f32 * TILE_SIZE + UI_HEIGHT,
+    );
     let default_plugins = DefaultPlugins.set(WindowPlugin {
         primary_window: Some(Window {
             resolution: window_resolution.clone(),
@@ -25,16 +123,23 @@ fn main() {
         ..default()
     });
     App::new()
-        .add_event::<Tick>()
         .add_plugins(default_plugins)
-        .add_plugins((PlayerPlugin, MapPlugin, MonsterPlugin, VisibilityPlugin))
-        .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
-        // .add_plugins(RapierDebugRenderPlugin::default())
-        .init_resource::<Map>()
-        .add_systems(PreStartup, spawn_camera)
+        .add_plugins((
+            GamePlugin,
+            GlyphPlugin,
+            PlayerInputPlugin,
+            AiDebugPlugin,
+            GameOverScreenPlugin,
+            GameLogPanelPlugin,
+            HudPlugin,
+            TargetingPlugin,
+            ItemMenuPlugin,
+        ))
+        .insert_resource(GameRng::from_seed(seed))
         .run();
 }
 
-fn spawn_camera(mut commands: Commands) {
-    commands.spawn(Camera2dBundle::default());
+fn arg_value<T: FromStr>(args: &[String], flag: &str) -> Option<T> {
+    let idx = args.iter().position(|arg| arg == flag)?;
+    args.get(idx + 1)?.parse().ok()
 }
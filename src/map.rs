@@ -1,74 +1,63 @@
 use crate::components::*;
-use crate::events::*;
-use crate::monsters::*;
-use crate::move_player;
+use crate::random::GameRng;
 use crate::rect::*;
 use crate::resources::*;
+use crate::turn::*;
 use bevy::color::Color;
 use bevy::color::Srgba;
 use bevy::prelude::{
-    default, on_event, App, Commands, Entity, IntoSystemConfigs, JustifyText, ParamSet, Plugin,
-    Query, Res, ResMut, Startup, Text, Text2dBundle, TextStyle, Transform, Update, Vec3, Window,
-    With,
+    default, App, Commands, Entity, IntoSystemConfigs, OnEnter, ParamSet, Plugin, Query, Res,
+    ResMut, SystemSet, Transform, Update, Vec3, With,
 };
-use bevy_rapier2d::prelude::*;
 use rand::Rng;
 use std::cmp::{max, min};
+use std::collections::HashSet;
 
 pub struct MapPlugin;
 
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            Startup,
-            (
-                apply_map,
-                populate_map_resources.before(apply_map),
-                create_map.before(populate_map_resources),
-            ),
-        )
-        .add_systems(
-            Update,
-            populate_blocked
-                .before(monster_ai)
-                .after(move_player)
-                .run_if(on_event::<Tick>()),
-        );
+        app.init_resource::<TerrainRevision>()
+            .add_systems(
+                OnEnter(GameState::Playing),
+                (
+                    apply_map,
+                    populate_map_resources.before(apply_map),
+                    create_map.before(populate_map_resources),
+                )
+                    .in_set(MapGenSet),
+            )
+            .add_systems(Update, populate_blocked.in_set(TurnSet::PlayerTurn));
     }
 }
 
+/// Builds the dungeon at the start of every run. Whatever goes into it comes after.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MapGenSet;
+
+/// Walls and every tile someone stands on are blocked.
 fn populate_blocked(
-    mut query_tiles: Query<&mut Tile>,
+    mut query_tiles: Query<(Entity, &mut Tile)>,
     query_monsters: Query<&Monster>,
     query_player_pos: Query<&Position, With<Player>>,
     map: Res<Map>,
 ) {
-    // This is problem
-    for mut tile in query_tiles.iter_mut() {
-        if tile.blocked == true {
-            tile.blocked = false;
-        }
-    }
-
-    for mut tile in query_tiles.iter_mut() {
-        if tile.tiletype == TileType::Wall {
-            tile.blocked = true;
+    let player_pos = query_player_pos.single();
+    let occupied: HashSet<Entity> = query_monsters
+        .iter()
+        .map(|monster| monster.occupied_tile)
+        .chain([map.tiles[get_tile_idx(player_pos.x, player_pos.y)]])
+        .collect();
+    for (tile_ent, mut tile) in query_tiles.iter_mut() {
+        let blocked = tile.tiletype == TileType::Wall || occupied.contains(&tile_ent);
+        if tile.blocked != blocked {
+            tile.blocked = blocked;
         }
     }
-
-    for monster in query_monsters.iter() {
-        query_tiles.get_mut(monster.occupied_tile).unwrap().blocked = true;
-    }
-
-    let player_pos = query_player_pos.single();
-    query_tiles
-        .get_mut(map.tiles[get_tile_idx(player_pos.x as usize, player_pos.y as usize)])
-        .unwrap()
-        .blocked = true;
 }
 
 pub fn get_tile_idx(idx_x: usize, idx_y: usize) -> usize {
-    idx_x + 80 * idx_y
+    idx_x + MAP_WIDTH * idx_y
 }
 
 fn populate_map_resources(
@@ -86,16 +75,17 @@ fn populate_map_resources(
     }
 }
 
-fn create_map(mut commands: Commands, query_window: Query<&Window>, map: ResMut<Map>) {
-    let window = query_window.single();
-    let y_max = window.resolution.height() / 2.0;
-    let y_min = window.resolution.height() / -2.0 + map.font_size / 2.0;
-    let x_max = window.resolution.width() / 2.0;
-    let x_min = window.resolution.width() / -2.0 + map.font_size / 2.0;
-    let x_range = (x_min as i32..x_max as i32).step_by(map.font_size as usize);
+fn create_map(mut commands: Commands, map: ResMut<Map>, mut rng: ResMut<GameRng>) {
+    let map_height = map.tile_res.height as f32 * map.tile_size;
+    let map_width = map.tile_res.width as f32 * map.tile_size;
+    let y_max = map_height / 2.0;
+    let y_min = map_height / -2.0 + map.tile_size / 2.0;
+    let x_max = map_width / 2.0;
+    let x_min = map_width / -2.0 + map.tile_size / 2.0;
+    let x_range = (x_min as i32..x_max as i32).step_by(map.tile_size as usize);
 
     for (iy, y) in (y_min as i32..y_max as i32)
-        .step_by(map.font_size as usize)
+        .step_by(map.tile_size as usize)
         .enumerate()
     {
         for (ix, x) in x_range.clone().enumerate() {
@@ -110,7 +100,6 @@ fn create_map(mut commands: Commands, query_window: Query<&Window>, map: ResMut<
                     translation: Vec3::new(x as f32, y as f32, 1.0),
                     ..default()
                 },
-                Collider::cuboid(5.0, 5.0),
             ));
         }
     }
@@ -122,8 +111,6 @@ fn create_map(mut commands: Commands, query_window: Query<&Window>, map: ResMut<
     const MIN_SIZE: i32 = 6;
     const MAX_SIZE: i32 = 10;
 
-    let mut rng = rand::thread_rng();
-
     let tile_width = map.tile_res.width;
     let tile_height = map.tile_res.height;
     for _ in 0..MAX_ROOMS {
@@ -139,36 +126,31 @@ fn create_map(mut commands: Commands, query_window: Query<&Window>, map: ResMut<
             }
         }
         if ok {
-            commands.spawn(Room {
-                rect: new_room.clone(),
-            });
+            commands.spawn(Room { rect: new_room });
             rooms.push(new_room);
         }
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn apply_map(
-    mut set: ParamSet<(Query<(Entity, &mut Tile, &Transform)>, Query<&mut Tile>)>,
+    mut set: ParamSet<(Query<(Entity, &Tile)>, Query<&mut Tile>)>,
     query_room: Query<&Room>,
     map: ResMut<Map>,
+    mut terrain: ResMut<TerrainRevision>,
     mut commands: Commands,
+    mut rng: ResMut<GameRng>,
 ) {
-    let text_style = TextStyle {
-        font: map.font.clone(),
-        font_size: map.font_size,
-        color: Color::Srgba(Srgba {
-            red: 1.0,
-            green: 1.0,
-            blue: 1.0,
-            alpha: 0.0,
-        }),
-    };
-
-    let mut rng = rand::thread_rng();
-
-    let mut i = 0;
+    terrain.0 += 1;
+    let unseen = Color::Srgba(Srgba {
+        red: 1.0,
+        green: 1.0,
+        blue: 1.0,
+        alpha: 0.0,
+    });
+
     let (mut old_x, mut old_y) = (0, 0);
-    for room in map.rooms.clone() {
+    for (i, room) in map.rooms.clone().into_iter().enumerate() {
         let new_room = query_room.get(room).unwrap().rect;
         apply_room_to_map(&new_room, &map, set.p1());
         if i > 0 {
@@ -181,36 +163,17 @@ fn apply_map(
             }
         }
         (old_x, old_y) = new_room.center();
-        i += 1;
     }
 
-    for (ent, tile, transform) in set.p0().iter() {
-        match tile.tiletype {
-            TileType::Wall => {
-                commands.entity(ent).insert(Text2dBundle {
-                    text: Text::from_section('#', text_style.clone())
-                        .with_justify(JustifyText::Center),
-                    transform: Transform::from_xyz(
-                        transform.translation.x,
-                        transform.translation.y,
-                        1.0,
-                    ),
-                    ..default()
-                });
-            }
-            TileType::Floor => {
-                commands.entity(ent).insert(Text2dBundle {
-                    text: Text::from_section('.', text_style.clone())
-                        .with_justify(JustifyText::Center),
-                    transform: Transform::from_xyz(
-                        transform.translation.x,
-                        transform.translation.y,
-                        1.0,
-                    ),
-                    ..default()
-                });
-            }
-        }
+    for (ent, tile) in set.p0().iter() {
+        let glyph = match tile.tiletype {
+            TileType::Wall => '#',
+            TileType::Floor => '.',
+        };
+        commands.entity(ent).insert(Renderable {
+            glyph,
+            color: unseen,
+        });
     }
 }
 
@@ -248,3 +211,53 @@ fn apply_vertical_tunnel(map: &ResMut<Map>, y1: i32, y2: i32, x: i32, mut query:
         }
     }
 }
+
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+    use bevy::ecs::system::SystemState;
+    use bevy::prelude::World;
+
+    /// A world holding a map drawn from `rows`, one string per row starting at y = 0. `#` is
+    /// a wall and anything else is floor. Everything outside `rows` is wall.
+    pub fn test_world(rows: &[&str]) -> World {
+        let mut world = World::new();
+        let mut map = Map::default();
+        for y in 0..MAP_HEIGHT {
+            for x in 0..MAP_WIDTH {
+                let floor = rows
+                    .get(y)
+                    .and_then(|row| row.as_bytes().get(x))
+                    .is_some_and(|&glyph| glyph != b'#');
+                let tile = world.spawn((
+                    Tile {
+                        tiletype: if floor {
+                            TileType::Floor
+                        } else {
+                            TileType::Wall
+                        },
+                        visibletype: VisibleType::Invisible,
+                        blocked: !floor,
+                    },
+                    Position { x, y },
+                ));
+                map.tiles.push(tile.id());
+            }
+        }
+        world.insert_resource(map);
+        world
+    }
+
+    /// Runs `f` with the world's map and a query over its tiles.
+    pub fn with_tiles<R>(world: &mut World, f: impl FnOnce(&Map, &Query<&Tile>) -> R) -> R {
+        let mut state = SystemState::<(Res<Map>, Query<&Tile>)>::new(world);
+        let (map, query_tiles) = state.get(world);
+        f(&map, &query_tiles)
+    }
+
+    /// Marks the tile at `(x, y)` as standing room only, like a monster is on it.
+    pub fn block(world: &mut World, (x, y): (usize, usize)) {
+        let tile = world.resource::<Map>().tiles[get_tile_idx(x, y)];
+        world.get_mut::<Tile>(tile).unwrap().blocked = true;
+    }
+}
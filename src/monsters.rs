@@ -1,5 +1,15 @@
+use crate::dijkstra::*;
+use crate::effects::*;
 use crate::events::*;
-use crate::resources::Map;
+use crate::initiative::*;
+use crate::los::*;
+use crate::map::MapGenSet;
+use crate::pathfinding::*;
+use crate::random::GameRng;
+use crate::raws::*;
+use crate::resources::*;
+use crate::spawn_table::*;
+use crate::turn::*;
 use crate::{components::*, get_tile_idx};
 use bevy::prelude::*;
 use rand::Rng;
@@ -8,18 +18,25 @@ pub struct MonsterPlugin;
 
 impl Plugin for MonsterPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(PostStartup, add_monsters);
+        app.init_resource::<MonsterRaws>()
+            .init_resource::<SpawnTable>()
+            .add_event::<MoraleShock>()
+            .add_systems(OnEnter(GameState::Playing), add_monsters.after(MapGenSet));
         app.add_systems(
             Update,
             (
-                unset_occupied_tiles
-                    .before(set_occupied_tiles)
-                    .run_if(on_event::<Tick>()),
-                set_occupied_tiles.run_if(on_event::<Tick>()),
-                monster_ai
-                    .before(set_occupied_tiles)
-                    .after(unset_occupied_tiles)
-                    .run_if(on_event::<Tick>()),
+                hear_noises.in_set(TurnSet::PlayerTurn),
+                shake_morale.before(ActingSet),
+                (
+                    unset_occupied_tiles,
+                    update_ai_states,
+                    alert_packs,
+                    monster_ai,
+                    set_occupied_tiles,
+                )
+                    .chain()
+                    .in_set(TurnSet::MonsterTurn)
+                    .in_set(ActingSet),
             ),
         );
     }
@@ -43,224 +60,680 @@ pub fn set_occupied_tiles(
     }
 }
 
-fn add_monsters(
+pub fn add_monsters(
     mut commands: Commands,
     query_rooms: Query<(Entity, &Room)>,
     query_transform: Query<&Transform>,
     map: Res<Map>,
+    raws: Res<MonsterRaws>,
+    spawn_table: Res<SpawnTable>,
+    mut rng: ResMut<GameRng>,
 ) {
-    for (_ent, room) in query_rooms.iter().skip(1) {
-        let center_tile = room.rect.center();
-        let tile_ent = map.tiles[get_tile_idx(center_tile.0 as usize, center_tile.1 as usize)];
-        let tile_trans = query_transform.get(tile_ent).unwrap();
-        let occupied_tile = map.tiles[get_tile_idx(center_tile.0 as usize, center_tile.1 as usize)];
-        commands.entity(occupied_tile).insert(Occupied);
-        let mut rng = rand::thread_rng();
-        let glyph: char;
-        let name: String;
-
-        if rng.gen_range(0..2) == 1 {
-            name = "Goblin".to_string();
-            glyph = 'g';
-        } else {
-            name = "Ogre".to_string();
-            glyph = 'o';
+    // The first room is where the player starts.
+    for room in map.rooms.iter().skip(1) {
+        let (_ent, room) = query_rooms.get(*room).unwrap();
+        for group in roll_room_spawns(&spawn_table, &raws, map.depth, &room.rect, &mut rng) {
+            let members: Vec<Entity> = group
+                .into_iter()
+                .map(|(template, tile)| {
+                    spawn_monster(
+                        &mut commands,
+                        template,
+                        tile,
+                        &map,
+                        &query_transform,
+                        &mut rng,
+                    )
+                })
+                .collect();
+            if members.len() > 1 {
+                for &member in members.iter() {
+                    commands.entity(member).insert(Pack(members[0]));
+                }
+            }
         }
+    }
+}
 
-        commands.spawn((
-            Text2dBundle {
-                text: Text::from_section(
-                    glyph,
-                    TextStyle {
-                        font: map.font.clone(),
-                        font_size: map.font_size,
-                        color: Color::Srgba(Srgba {
-                            red: 1.0,
-                            green: 0.0,
-                            blue: 0.0,
-                            alpha: 1.0,
-                        }),
-                    },
-                )
-                .with_justify(JustifyText::Center),
-                transform: Transform::from_xyz(
-                    tile_trans.translation.x,
-                    tile_trans.translation.y,
-                    2.0,
-                ),
-                visibility: Visibility::Hidden,
-                ..default()
+pub fn spawn_monster(
+    commands: &mut Commands,
+    template: &MonsterTemplate,
+    (x, y): (usize, usize),
+    map: &Map,
+    query_transform: &Query<&Transform>,
+    rng: &mut GameRng,
+) -> Entity {
+    let occupied_tile = map.tiles[get_tile_idx(x, y)];
+    let tile_trans = query_transform.get(occupied_tile).unwrap();
+    commands.entity(occupied_tile).insert(Occupied);
+
+    let mut monster = commands.spawn((
+        Renderable {
+            glyph: template.glyph,
+            color: template.color(),
+        },
+        Transform::from_xyz(tile_trans.translation.x, tile_trans.translation.y, 2.0),
+        Visibility::Hidden,
+        Position { x, y },
+        Monster { occupied_tile },
+        template.ai,
+        template.idle,
+        template.idle.state(),
+        Post { x, y },
+        Viewshed {
+            visible_tiles: Vec::new(),
+            range: template.vision,
+        },
+        (
+            Morale {
+                current: template.stats.morale,
+                max: template.stats.morale,
             },
-            Position {
-                x: center_tile.0 as usize,
-                y: center_tile.1 as usize,
+            CombatStats {
+                max_hp: template.stats.hp,
+                hp: template.stats.hp,
+                power: template.stats.power,
+                defense: template.stats.defense,
             },
-            Monster { occupied_tile },
-            Name::new(name),
-        ));
+            XpReward(template.xp),
+        ),
+        Speed(template.stats.speed),
+        Energy(rng.gen_range(0..ACTION_THRESHOLD)),
+        Name::new(template.name.clone()),
+    ));
+    if let Some(inflicts) = template.inflicts {
+        monster.insert(inflicts);
+    }
+    monster.id()
+}
+
+/// Chance out of 100 that a sleeping monster wakes when it hears a noise.
+const WAKE_CHANCE: i32 = 50;
+
+/// How long a monster keeps looking around where it lost the player.
+const SEARCH_TURNS: i32 = 5;
+
+/// How far from the player's last known tile a search wanders.
+const SEARCH_RADIUS: usize = 3;
+
+/// How many turns a monster runs for once its morale breaks.
+const FLEE_TURNS: i32 = 10;
+
+/// How far a monster that spots the player can call out to the rest of its pack.
+const PACK_ALERT_RADIUS: usize = 10;
+
+/// A monster is outmatched by a player this many times as dangerous as itself.
+const OUTMATCHED: i32 = 4;
+
+/// Morale lost on catching sight of a player that outmatches the monster.
+const OUTMATCHED_SHOCK: i32 = 5;
+
+/// Rooms can be further apart than a hunt would ever chase, so wanderers look further.
+const WANDER_SEARCH_DEPTH: usize = 120;
+
+/// Sleeping monsters that hear something may wake up.
+fn hear_noises(
+    mut noises: EventReader<Noise>,
+    mut query_monsters: Query<(&Position, &mut AiState), With<Monster>>,
+    mut rng: ResMut<GameRng>,
+) {
+    for noise in noises.read() {
+        for (position, mut state) in query_monsters.iter_mut() {
+            let dx = position.x as i32 - noise.x as i32;
+            let dy = position.y as i32 - noise.y as i32;
+            if *state == AiState::Sleeping
+                && dx * dx + dy * dy <= noise.radius * noise.radius
+                && rng.gen_range(0..100) < WAKE_CHANCE
+            {
+                *state = AiState::Idle;
+            }
+        }
     }
 }
 
+/// Knocks morale down, sending the monster running once it hits zero.
+fn shake_morale(
+    mut shocks: EventReader<MoraleShock>,
+    mut query_monsters: Query<(&mut Morale, &mut AiState), With<Monster>>,
+) {
+    for shock in shocks.read() {
+        let Ok((mut morale, mut state)) = query_monsters.get_mut(shock.target) else {
+            continue;
+        };
+        morale.current = (morale.current - shock.amount).max(0);
+        if morale.current == 0 && !matches!(*state, AiState::Fleeing { .. }) {
+            *state = AiState::Fleeing {
+                turns_left: FLEE_TURNS,
+            };
+        }
+    }
+}
+
+/// Moves each acting monster between sleeping, idling, wandering, hunting, searching and
+/// going home, depending on what it can see. Fleeing monsters keep running until they calm down.
+#[allow(clippy::type_complexity)]
+pub fn update_ai_states(
+    mut query_monsters: Query<
+        (
+            &Viewshed,
+            &Position,
+            &Post,
+            &IdleBehaviour,
+            &CombatStats,
+            &mut Morale,
+            &mut AiState,
+        ),
+        With<Monster>,
+    >,
+    query_player: Query<(&Position, &CombatStats), With<Player>>,
+    turn_order: Res<TurnOrder>,
+    map: Res<Map>,
+    mut shocks: EventWriter<MoraleShock>,
+) {
+    let (player_pos, player_stats) = query_player.single();
+    let player_tile = map.tiles[get_tile_idx(player_pos.x, player_pos.y)];
+    for &monster in turn_order.acting.iter() {
+        let Ok((viewshed, position, post, idle, stats, mut morale, mut state)) =
+            query_monsters.get_mut(monster)
+        else {
+            continue;
+        };
+        let sees_player =
+            *state != AiState::Sleeping && viewshed.visible_tiles.contains(&player_tile);
+        let next_to_player =
+            position.x.abs_diff(player_pos.x) <= 1 && position.y.abs_diff(player_pos.y) <= 1;
+
+        let from = (position.x, position.y);
+
+        let spotted_player = sees_player
+            && !matches!(
+                *state,
+                AiState::Hunting { .. } | AiState::Searching { .. } | AiState::Fleeing { .. }
+            );
+        if spotted_player && threat(player_stats) >= threat(stats) * OUTMATCHED {
+            shocks.send(MoraleShock {
+                target: monster,
+                amount: OUTMATCHED_SHOCK,
+            });
+        }
+
+        *state = match *state {
+            // Pulled itself together, with half its nerve back.
+            AiState::Fleeing { turns_left } if turns_left <= 0 => {
+                morale.current = morale.max / 2;
+                if sees_player {
+                    AiState::Hunting {
+                        last_seen: (player_pos.x, player_pos.y),
+                    }
+                } else {
+                    idle.lost_player_state()
+                }
+            }
+            AiState::Fleeing { turns_left } => AiState::Fleeing {
+                turns_left: turns_left - 1,
+            },
+            _ if sees_player => AiState::Hunting {
+                last_seen: (player_pos.x, player_pos.y),
+            },
+            AiState::Sleeping if next_to_player => AiState::Hunting {
+                last_seen: (player_pos.x, player_pos.y),
+            },
+            // Out of sight: keep going to where the player was last seen, then look around.
+            AiState::Hunting { last_seen }
+                if from.0.abs_diff(last_seen.0) <= 1 && from.1.abs_diff(last_seen.1) <= 1 =>
+            {
+                AiState::Searching {
+                    last_seen,
+                    turns_left: SEARCH_TURNS,
+                }
+            }
+            AiState::Searching { turns_left, .. } if turns_left <= 0 => idle.lost_player_state(),
+            AiState::Searching {
+                last_seen,
+                turns_left,
+            } => AiState::Searching {
+                last_seen,
+                turns_left: turns_left - 1,
+            },
+            AiState::ReturningToPost if position.x == post.x && position.y == post.y => {
+                idle.state()
+            }
+            state => state,
+        };
+    }
+}
+
+/// Monsters that can see the player tell packmates nearby where it is. Anyone not running
+/// away joins the hunt.
+fn alert_packs(
+    mut query_monsters: Query<(&Pack, &Position, &Viewshed, &mut AiState), With<Monster>>,
+    query_player: Query<&Position, With<Player>>,
+    turn_order: Res<TurnOrder>,
+    map: Res<Map>,
+) {
+    let player_pos = query_player.single();
+    let player_tile = map.tiles[get_tile_idx(player_pos.x, player_pos.y)];
+    let spotters: Vec<(Pack, (usize, usize))> = turn_order
+        .acting
+        .iter()
+        .filter_map(|&monster| query_monsters.get(monster).ok())
+        .filter(|(_, _, viewshed, state)| {
+            **state != AiState::Sleeping && viewshed.visible_tiles.contains(&player_tile)
+        })
+        .map(|(&pack, position, _, _)| (pack, (position.x, position.y)))
+        .collect();
+
+    for (pack, from) in spotters {
+        for (&other_pack, position, _, mut state) in query_monsters.iter_mut() {
+            let in_earshot = position.x.abs_diff(from.0) <= PACK_ALERT_RADIUS
+                && position.y.abs_diff(from.1) <= PACK_ALERT_RADIUS;
+            if other_pack == pack && in_earshot && !matches!(*state, AiState::Fleeing { .. }) {
+                *state = AiState::Hunting {
+                    last_seen: (player_pos.x, player_pos.y),
+                };
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 pub fn monster_ai(
     mut query_monsters: Query<
-        (&Visibility, &mut Position, &mut Transform),
+        (
+            Entity,
+            &AiBehaviour,
+            Option<&Pack>,
+            &Post,
+            &mut AiState,
+            &mut Position,
+            &mut Transform,
+            &mut Energy,
+        ),
         (With<Monster>, Without<Player>),
     >,
-    query_player: Query<&Position, With<Player>>,
+    turn_order: Res<TurnOrder>,
+    query_player: Query<(Entity, &Position), With<Player>>,
     query_tile: Query<&Transform, Without<Monster>>,
-    query_tile2: Query<&Tile>,
+    mut query_tiles: Query<&mut Tile>,
+    query_rooms: Query<&Room>,
     map: Res<Map>,
+    flow_maps: Res<FlowMaps>,
+    mut rng: ResMut<GameRng>,
+    mut attacks: EventWriter<Attack>,
+    query_effects: Query<(Has<Asleep>, Has<Confused>)>,
 ) {
-    let player_pos = query_player.get_single().unwrap();
-    for (visibility, mut position, mut transform) in query_monsters.iter_mut() {
-        let monst_x_plus1 = query_tile
-            .get(map.tiles[get_tile_idx(position.x + 1, position.y)])
-            .unwrap()
-            .translation
-            .x;
-        let monst_y_plus1 = query_tile
-            .get(map.tiles[get_tile_idx(position.x, position.y + 1)])
-            .unwrap()
-            .translation
-            .y;
-        let monst_x_minus1 = query_tile
-            .get(map.tiles[get_tile_idx(position.x - 1, position.y)])
-            .unwrap()
-            .translation
-            .x;
-        let monst_y_minus1 = query_tile
-            .get(map.tiles[get_tile_idx(position.x, position.y - 1)])
-            .unwrap()
-            .translation
-            .y;
-        match visibility {
-            Visibility::Visible => {
-                let angle =
-                    get_angle(player_pos.x, player_pos.y, position.x, position.y).to_degrees();
-                println!("{angle}");
-                if angle < 22.5 && angle >= -22.5 {
-                    if query_tile2
-                        .get(map.tiles[get_tile_idx(position.x + 1, position.y)])
-                        .unwrap()
-                        .blocked
-                        == false
-                    {
-                        transform.translation.x = monst_x_plus1;
-                        position.x = position.x + 1;
-                    }
-                } else if angle < 67.5 && angle >= 22.5 {
-                    if query_tile2
-                        .get(map.tiles[get_tile_idx(position.x + 1, position.y + 1)])
-                        .unwrap()
-                        .blocked
-                        == false
-                    {
-                        transform.translation.x = monst_x_plus1;
-                        transform.translation.y = monst_y_plus1;
-                        position.x = position.x + 1;
-                        position.y = position.y + 1;
-                    }
-                } else if angle < 112.5 && angle >= 67.5 {
-                    if query_tile2
-                        .get(map.tiles[get_tile_idx(position.x, position.y + 1)])
-                        .unwrap()
-                        .blocked
-                        == false
-                    {
-                        transform.translation.y = monst_y_plus1;
-                        position.y = position.y + 1;
-                    }
-                } else if angle < 157.5 && angle >= 112.5 {
-                    if query_tile2
-                        .get(map.tiles[get_tile_idx(position.x - 1, position.y + 1)])
-                        .unwrap()
-                        .blocked
-                        == false
-                    {
-                        transform.translation.x = monst_x_minus1;
-                        transform.translation.y = monst_y_plus1;
-                        position.x = position.x - 1;
-                        position.y = position.y + 1;
-                    }
-                } else if (angle < -157.5 && angle > -180.0) || (angle >= 157.5 && angle <= 180.0) {
-                    if query_tile2
-                        .get(map.tiles[get_tile_idx(position.x - 1, position.y)])
-                        .unwrap()
-                        .blocked
-                        == false
-                    {
-                        transform.translation.x = monst_x_minus1;
-                        position.x = position.x - 1;
-                    }
-                } else if angle < -112.5 && angle >= -157.5 {
-                    if query_tile2
-                        .get(map.tiles[get_tile_idx(position.x - 1, position.y - 1)])
-                        .unwrap()
-                        .blocked
-                        == false
-                    {
-                        transform.translation.x = monst_x_minus1;
-                        transform.translation.y = monst_y_minus1;
-                        position.x = position.x - 1;
-                        position.y = position.y - 1;
-                    }
-                } else if angle < -67.5 && angle >= -112.5 {
-                    if query_tile2
-                        .get(map.tiles[get_tile_idx(position.x, position.y - 1)])
-                        .unwrap()
-                        .blocked
-                        == false
-                    {
-                        transform.translation.y = monst_y_minus1;
-                        position.y = position.y - 1;
-                    }
-                } else if angle < -22.5 && angle >= -67.5 {
-                    if query_tile2
-                        .get(map.tiles[get_tile_idx(position.x + 1, position.y - 1)])
-                        .unwrap()
-                        .blocked
-                        == false
-                    {
-                        transform.translation.x = monst_x_plus1;
-                        transform.translation.y = monst_y_minus1;
-                        position.y = position.y - 1;
-                        position.x = position.x + 1;
-                    }
+    let (player, player_pos) = query_player.get_single().unwrap();
+    // Monsters go in turn order and never swap places, so who gets a contested tile is
+    // always the same for the same seed.
+    for &monster in turn_order.acting.iter() {
+        let query_tile2 = query_tiles.to_readonly();
+        let flank = query_monsters
+            .get(monster)
+            .ok()
+            .and_then(|(_, _, pack, ..)| pack.copied())
+            .and_then(|pack| {
+                let hunters: Vec<(Entity, (usize, usize))> = query_monsters
+                    .iter()
+                    .filter(|(_, _, other_pack, _, state, ..)| {
+                        other_pack == &Some(&pack) && matches!(**state, AiState::Hunting { .. })
+                    })
+                    .map(|(entity, _, _, _, _, position, ..)| (entity, (position.x, position.y)))
+                    .collect();
+                flank_tile(&map, &query_tile2, monster, &hunters, player_pos)
+            });
+        let Ok((_, ai, _, post, mut state, mut position, mut transform, mut energy)) =
+            query_monsters.get_mut(monster)
+        else {
+            continue;
+        };
+        energy.0 -= MOVE_COST;
+        let from = (position.x, position.y);
+        let (asleep, confused) = query_effects.get(monster).unwrap_or_default();
+
+        let step = match *state {
+            _ if asleep => None,
+            _ if confused => {
+                let steps = neighbours(&map, &query_tile2, from, from);
+                (!steps.is_empty()).then(|| steps[rng.gen_range(0..steps.len())].0)
+            }
+            AiState::Sleeping | AiState::Idle => None,
+            AiState::Hunting { last_seen } if last_seen != (player_pos.x, player_pos.y) => {
+                let step = step_towards(&map, &query_tile2, from, last_seen, MAX_SEARCH_DEPTH);
+                // No way through, so start looking around from here.
+                if step.is_none() {
+                    *state = AiState::Searching {
+                        last_seen,
+                        turns_left: SEARCH_TURNS,
+                    };
+                }
+                step
+            }
+            AiState::Hunting { .. } => match *ai {
+                AiBehaviour::Melee if distance(from, (player_pos.x, player_pos.y)) <= 1 => {
+                    attacks.send(Attack {
+                        attacker: monster,
+                        target: player,
+                        kind: AttackKind::Melee,
+                    });
+                    None
                 }
+                AiBehaviour::Melee => flank
+                    .and_then(|goal| step_towards(&map, &query_tile2, from, goal, MAX_SEARCH_DEPTH))
+                    .or_else(|| {
+                        flow_maps
+                            .approach_player
+                            .as_ref()
+                            .and_then(|approach| approach.best_next_step(&map, &query_tile2, from))
+                    })
+                    .or_else(|| {
+                        // Downhill is crowded, look for a way around the other monsters.
+                        let goal = (player_pos.x, player_pos.y);
+                        step_towards(&map, &query_tile2, from, goal, MAX_SEARCH_DEPTH)
+                    }),
+                AiBehaviour::Archer { range, keep_away } => {
+                    let target = (player_pos.x, player_pos.y);
+                    let has_line = |tile| line_of_fire(&map, &query_tile2, tile, target).is_clear();
+                    let action = ranged_action(
+                        &map,
+                        &query_tile2,
+                        &flow_maps,
+                        from,
+                        target,
+                        (range as usize, keep_away as usize),
+                        has_line,
+                    );
+                    shoot_or_step(action, &mut attacks, monster, player, AttackKind::Arrow)
+                }
+                AiBehaviour::Caster { range, keep_away } => {
+                    let target = (player_pos.x, player_pos.y);
+                    let has_line =
+                        |tile| line_of_sight(&map, &query_tile2, tile, target).is_clear();
+                    let action = ranged_action(
+                        &map,
+                        &query_tile2,
+                        &flow_maps,
+                        from,
+                        target,
+                        (range as usize, keep_away as usize),
+                        has_line,
+                    );
+                    shoot_or_step(action, &mut attacks, monster, player, AttackKind::Spell)
+                }
+            },
+            AiState::Searching { last_seen, .. } => {
+                let steps: Vec<(usize, usize)> = neighbours(&map, &query_tile2, from, from)
+                    .into_iter()
+                    .map(|(step, _cost)| step)
+                    .filter(|step| {
+                        step.0.abs_diff(last_seen.0) <= SEARCH_RADIUS
+                            && step.1.abs_diff(last_seen.1) <= SEARCH_RADIUS
+                    })
+                    .collect();
+                (!steps.is_empty()).then(|| steps[rng.gen_range(0..steps.len())])
             }
-            _ => {}
+            AiState::Wandering { destination } => {
+                let destination = match destination {
+                    Some(destination) if destination != from => destination,
+                    _ => {
+                        let room = map.rooms[rng.gen_range(0..map.rooms.len())];
+                        let (x, y) = query_rooms.get(room).unwrap().rect.center();
+                        (x as usize, y as usize)
+                    }
+                };
+                let step = step_towards(&map, &query_tile2, from, destination, WANDER_SEARCH_DEPTH);
+                // Stuck, so try somewhere else next time.
+                *state = AiState::Wandering {
+                    destination: step.map(|_| destination),
+                };
+                step
+            }
+            AiState::ReturningToPost => {
+                let goal = (post.x, post.y);
+                step_towards(&map, &query_tile2, from, goal, WANDER_SEARCH_DEPTH)
+            }
+            AiState::Fleeing { .. } => flow_maps
+                .flee_player
+                .as_ref()
+                .and_then(|flee| flee.best_next_step(&map, &query_tile2, from)),
+        };
+
+        if let Some(step) = step {
+            // Claim the tile straight away, so whoever moves next goes around.
+            query_tiles
+                .get_mut(map.tiles[get_tile_idx(from.0, from.1)])
+                .unwrap()
+                .blocked = false;
+            query_tiles
+                .get_mut(map.tiles[get_tile_idx(step.0, step.1)])
+                .unwrap()
+                .blocked = true;
+            move_monster(&mut position, &mut transform, step, &map, &query_tile);
+        }
+    }
+}
+
+/// What a monster that fights from a distance does this turn.
+enum RangedAction {
+    Shoot,
+    Step((usize, usize)),
+    Hold,
+}
+
+/// Backs off when the player is within `keep_away`, shoots if there's a line to the player
+/// within `range`, and otherwise moves somewhere it will have one.
+fn ranged_action(
+    map: &Map,
+    query_tiles: &Query<&Tile>,
+    flow_maps: &FlowMaps,
+    from: (usize, usize),
+    target: (usize, usize),
+    (range, keep_away): (usize, usize),
+    has_line: impl Fn((usize, usize)) -> bool,
+) -> RangedAction {
+    if distance(from, target) <= keep_away {
+        let away = flow_maps
+            .flee_player
+            .as_ref()
+            .and_then(|flee| flee.best_next_step(map, query_tiles, from));
+        if let Some(step) = away {
+            return RangedAction::Step(step);
+        }
+    }
+    if distance(from, target) <= range && has_line(from) {
+        return RangedAction::Shoot;
+    }
+
+    // Try a step to the side for a clear shot before closing in.
+    neighbours(map, query_tiles, from, from)
+        .into_iter()
+        .map(|(tile, _cost)| tile)
+        .find(|&tile| (keep_away + 1..=range).contains(&distance(tile, target)) && has_line(tile))
+        .or_else(|| {
+            flow_maps
+                .approach_player
+                .as_ref()
+                .and_then(|approach| approach.best_next_step(map, query_tiles, from))
+        })
+        .map_or(RangedAction::Hold, RangedAction::Step)
+}
+
+fn shoot_or_step(
+    action: RangedAction,
+    attacks: &mut EventWriter<Attack>,
+    attacker: Entity,
+    target: Entity,
+    kind: AttackKind,
+) -> Option<(usize, usize)> {
+    match action {
+        RangedAction::Shoot => {
+            attacks.send(Attack {
+                attacker,
+                target,
+                kind,
+            });
+            None
         }
+        RangedAction::Step(step) => Some(step),
+        RangedAction::Hold => None,
     }
 }
 
-// fn try_move_monster(
-//     position_x: usize,
-//     position_y: usize,
-//     map: Res<Map>,
-//     query_tile: Query<&Tile>,
-//     query_tile_transform: Query<&Transform, Without<Monster>>,
-//     monster_transform: Mut<Transform>,
-//     new_x: f32,
-//     new_y: f32,
-// ) {
-//     if query_tile
-//         .get(map.tiles[get_tile_idx(position_x, position_y)])
-//         .unwrap()
-//         .blocked
-//         == false
-//     {
-//         monster_transform.translation.x = new_x;
-//         position.x = position.x + 1;
-//     }
-// }
-
-fn get_angle(
-    player_pos_x: usize,
-    player_pos_y: usize,
-    monster_pos_x: usize,
-    monster_pos_y: usize,
-) -> f32 {
-    let y = player_pos_y as f32 - monster_pos_y as f32;
-    let x = player_pos_x as f32 - monster_pos_x as f32;
-    y.atan2(x)
+/// Rough idea of how dangerous something is in a fight.
+fn threat(stats: &CombatStats) -> i32 {
+    stats.hp * stats.power
+}
+
+/// Steps between two tiles, counting diagonals as one.
+pub fn distance(a: (usize, usize), b: (usize, usize)) -> usize {
+    a.0.abs_diff(b.0).max(a.1.abs_diff(b.1))
+}
+
+/// The free tile next to the player that `me` should head for, so a hunting pack surrounds
+/// the player instead of queueing up behind each other. Whoever is closest picks first.
+fn flank_tile(
+    map: &Map,
+    query_tiles: &Query<&Tile>,
+    me: Entity,
+    hunters: &[(Entity, (usize, usize))],
+    player_pos: &Position,
+) -> Option<(usize, usize)> {
+    let player = (player_pos.x, player_pos.y);
+    let mut open: Vec<(usize, usize)> = neighbours(map, query_tiles, player, player)
+        .into_iter()
+        .map(|(tile, _cost)| tile)
+        .collect();
+
+    let mut hunters: Vec<(Entity, (usize, usize))> = hunters
+        .iter()
+        .copied()
+        .filter(|&(_, position)| distance(position, player) > 1)
+        .collect();
+    hunters.sort_by_key(|&(entity, position)| (distance(position, player), entity));
+
+    for (entity, position) in hunters {
+        let (i, _) = open
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, &tile)| distance(tile, position))?;
+        let tile = open.swap_remove(i);
+        if entity == me {
+            return Some(tile);
+        }
+    }
+    None
+}
+
+/// First step of the A* path to `goal`, if that tile is free to walk onto.
+fn step_towards(
+    map: &Map,
+    query_tiles: &Query<&Tile>,
+    from: (usize, usize),
+    goal: (usize, usize),
+    max_depth: usize,
+) -> Option<(usize, usize)> {
+    let path = a_star(map, query_tiles, from, goal, max_depth)?;
+    let &step = path.first()?;
+    let tile = query_tiles
+        .get(map.tiles[get_tile_idx(step.0, step.1)])
+        .ok()?;
+    (step != from && !tile.blocked).then_some(step)
+}
+
+fn move_monster(
+    position: &mut Position,
+    transform: &mut Transform,
+    (x, y): (usize, usize),
+    map: &Map,
+    query_tile: &Query<&Transform, Without<Monster>>,
+) {
+    let tile_transform = query_tile.get(map.tiles[get_tile_idx(x, y)]).unwrap();
+    transform.translation.x = tile_transform.translation.x;
+    transform.translation.y = tile_transform.translation.y;
+    position.x = x;
+    position.y = y;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::testing::test_world;
+    use bevy::ecs::schedule::Schedule;
+
+    /// A monster at (2, 1) in sight of the player at (6, 1), after one round of deciding
+    /// what to do.
+    fn face_player(monster_stats: CombatStats, morale: i32) -> AiState {
+        let mut world = test_world(&["#########", "#.......#", "#########"]);
+        world.init_resource::<Events<MoraleShock>>();
+        let player_tile = world.resource::<Map>().tiles[get_tile_idx(6, 1)];
+        world.spawn((
+            Player,
+            Position { x: 6, y: 1 },
+            CombatStats {
+                max_hp: 30,
+                hp: 30,
+                power: 5,
+                defense: 2,
+            },
+        ));
+        let monster = world
+            .spawn((
+                Monster {
+                    occupied_tile: player_tile,
+                },
+                Viewshed {
+                    visible_tiles: vec![player_tile],
+                    range: 8,
+                },
+                Position { x: 2, y: 1 },
+                Post { x: 2, y: 1 },
+                IdleBehaviour::Guard,
+                monster_stats,
+                Morale {
+                    current: morale,
+                    max: morale,
+                },
+                AiState::Idle,
+            ))
+            .id();
+        world.insert_resource(TurnOrder {
+            acting: vec![monster],
+        });
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems((update_ai_states, shake_morale).chain());
+        schedule.run(&mut world);
+        *world.get::<AiState>(monster).unwrap()
+    }
+
+    #[test]
+    fn outmatched_monsters_run_on_sight() {
+        let weak = CombatStats {
+            max_hp: 4,
+            hp: 4,
+            power: 2,
+            defense: 0,
+        };
+        assert_eq!(
+            face_player(weak, OUTMATCHED_SHOCK),
+            AiState::Fleeing {
+                turns_left: FLEE_TURNS
+            }
+        );
+        // Shaken, but not enough to break.
+        assert!(matches!(
+            face_player(weak, OUTMATCHED_SHOCK + 1),
+            AiState::Hunting { .. }
+        ));
+    }
+
+    #[test]
+    fn a_fair_fight_does_not_shake_morale() {
+        let even = CombatStats {
+            max_hp: 30,
+            hp: 30,
+            power: 5,
+            defense: 2,
+        };
+        assert!(matches!(face_player(even, 1), AiState::Hunting { .. }));
+    }
 }
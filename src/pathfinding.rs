@@ -0,0 +1,224 @@
+use crate::components::*;
+use crate::map::get_tile_idx;
+use crate::resources::Map;
+use bevy::ecs::query::QueryFilter;
+use bevy::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/// How many steps long a path may get before A* gives up.
+pub const MAX_SEARCH_DEPTH: usize = 40;
+
+const STRAIGHT_COST: usize = 10;
+const DIAGONAL_COST: usize = 14;
+
+/// Shortest walkable path from `start` to `goal`, not including `start`. Occupied tiles are
+/// avoided, except for `goal` itself so monsters can path to whoever is standing there.
+pub fn a_star<F: QueryFilter>(
+    map: &Map,
+    query_tiles: &Query<&Tile, F>,
+    start: (usize, usize),
+    goal: (usize, usize),
+    max_depth: usize,
+) -> Option<Vec<(usize, usize)>> {
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<(usize, usize), (usize, usize)> = HashMap::new();
+    let mut best_cost: HashMap<(usize, usize), (usize, usize)> = HashMap::new();
+
+    open.push(Reverse((octile_distance(start, goal), 0, start)));
+    best_cost.insert(start, (0, 0));
+
+    while let Some(Reverse((_estimate, cost, current))) = open.pop() {
+        if current == goal {
+            let mut path = vec![current];
+            let mut step = current;
+            while let Some(&previous) = came_from.get(&step) {
+                if previous == start {
+                    break;
+                }
+                path.push(previous);
+                step = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        let (known_cost, depth) = best_cost[&current];
+        if cost > known_cost || depth >= max_depth {
+            continue;
+        }
+
+        for (next, step_cost) in neighbours(map, query_tiles, current, goal) {
+            let next_cost = cost + step_cost;
+            if best_cost
+                .get(&next)
+                .is_some_and(|&(known, _)| known <= next_cost)
+            {
+                continue;
+            }
+            best_cost.insert(next, (next_cost, depth + 1));
+            came_from.insert(next, current);
+            open.push(Reverse((
+                next_cost + octile_distance(next, goal),
+                next_cost,
+                next,
+            )));
+        }
+    }
+    None
+}
+
+/// Tiles a walker can step to from `from`, with the cost of the step. Diagonal steps may
+/// not squeeze past a wall corner.
+pub fn neighbours<F: QueryFilter>(
+    map: &Map,
+    query_tiles: &Query<&Tile, F>,
+    from: (usize, usize),
+    goal: (usize, usize),
+) -> Vec<((usize, usize), usize)> {
+    let mut steps = Vec::new();
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            if dx == 0 && dy == 0 {
+                continue;
+            }
+            let Some(next) = offset(map, from, dx, dy) else {
+                continue;
+            };
+            if next != goal && !is_walkable(map, query_tiles, next) {
+                continue;
+            }
+            if dx != 0 && dy != 0 {
+                let side_x = (next.0, from.1);
+                let side_y = (from.0, next.1);
+                if is_wall(map, query_tiles, side_x) || is_wall(map, query_tiles, side_y) {
+                    continue;
+                }
+                steps.push((next, DIAGONAL_COST));
+            } else {
+                steps.push((next, STRAIGHT_COST));
+            }
+        }
+    }
+    steps
+}
+
+pub fn offset(map: &Map, (x, y): (usize, usize), dx: i32, dy: i32) -> Option<(usize, usize)> {
+    let x = x as i32 + dx;
+    let y = y as i32 + dy;
+    if x < 0 || y < 0 || x >= map.tile_res.width as i32 || y >= map.tile_res.height as i32 {
+        return None;
+    }
+    Some((x as usize, y as usize))
+}
+
+fn is_walkable<F: QueryFilter>(
+    map: &Map,
+    query_tiles: &Query<&Tile, F>,
+    (x, y): (usize, usize),
+) -> bool {
+    query_tiles
+        .get(map.tiles[get_tile_idx(x, y)])
+        .is_ok_and(|tile| tile.tiletype == TileType::Floor && !tile.blocked)
+}
+
+fn is_wall<F: QueryFilter>(
+    map: &Map,
+    query_tiles: &Query<&Tile, F>,
+    (x, y): (usize, usize),
+) -> bool {
+    query_tiles
+        .get(map.tiles[get_tile_idx(x, y)])
+        .is_ok_and(|tile| tile.tiletype == TileType::Wall)
+}
+
+fn octile_distance(a: (usize, usize), b: (usize, usize)) -> usize {
+    let dx = a.0.abs_diff(b.0);
+    let dy = a.1.abs_diff(b.1);
+    STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::testing::*;
+
+    fn path(
+        rows: &[&str],
+        start: (usize, usize),
+        goal: (usize, usize),
+        max_depth: usize,
+    ) -> Option<Vec<(usize, usize)>> {
+        let mut world = test_world(rows);
+        with_tiles(&mut world, |map, query_tiles| {
+            a_star(map, query_tiles, start, goal, max_depth)
+        })
+    }
+
+    fn is_wall_at(rows: &[&str], (x, y): (usize, usize)) -> bool {
+        rows[y].as_bytes()[x] == b'#'
+    }
+
+    #[test]
+    fn goes_around_a_wall_the_short_way() {
+        let rows = [
+            "#########",
+            "#...#...#",
+            "#...#...#",
+            "#...#...#",
+            "#.......#",
+            "#########",
+        ];
+        let path = path(&rows, (2, 1), (6, 1), MAX_SEARCH_DEPTH).unwrap();
+        assert_eq!(path.len(), 8, "{path:?}");
+        assert!(path.contains(&(4, 4)));
+        assert_eq!(path.last(), Some(&(6, 1)));
+        assert!(path.iter().all(|&tile| !is_wall_at(&rows, tile)));
+    }
+
+    #[test]
+    fn never_cuts_a_wall_corner() {
+        let rows = ["######", "#.#..#", "#....#", "######"];
+        let path = path(&rows, (1, 1), (3, 1), MAX_SEARCH_DEPTH).unwrap();
+        let mut from = (1, 1);
+        for &to in path.iter() {
+            if from.0 != to.0 && from.1 != to.1 {
+                assert!(!is_wall_at(&rows, (to.0, from.1)), "{from:?} to {to:?}");
+                assert!(!is_wall_at(&rows, (from.0, to.1)), "{from:?} to {to:?}");
+            }
+            from = to;
+        }
+        assert_eq!(path.len(), 4, "{path:?}");
+    }
+
+    #[test]
+    fn unreachable_goal_has_no_path() {
+        let rows = ["#######", "#..#..#", "#..#..#", "#######"];
+        assert_eq!(path(&rows, (1, 1), (5, 2), MAX_SEARCH_DEPTH), None);
+    }
+
+    #[test]
+    fn gives_up_past_the_search_depth() {
+        let rows = ["##############", "#............#", "##############"];
+        assert_eq!(path(&rows, (1, 1), (12, 1), 5), None);
+        assert_eq!(
+            path(&rows, (1, 1), (12, 1), 11).map(|path| path.len()),
+            Some(11)
+        );
+    }
+
+    #[test]
+    fn paths_onto_an_occupied_goal_but_not_through_one() {
+        let rows = ["#######", "#.....#", "#######"];
+        let mut world = test_world(&rows);
+        block(&mut world, (3, 1));
+        let (to_blocker, past_blocker) = with_tiles(&mut world, |map, query_tiles| {
+            (
+                a_star(map, query_tiles, (1, 1), (3, 1), MAX_SEARCH_DEPTH),
+                a_star(map, query_tiles, (1, 1), (5, 1), MAX_SEARCH_DEPTH),
+            )
+        });
+        assert_eq!(to_blocker, Some(vec![(2, 1), (3, 1)]));
+        assert_eq!(past_blocker, None);
+    }
+}
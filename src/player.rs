@@ -1,78 +1,170 @@
 use crate::components::*;
+use crate::effects::*;
 use crate::events::*;
+use crate::gamelog::GameLog;
+use crate::initiative::*;
 use crate::map::*;
+use crate::random::GameRng;
 use crate::resources::*;
+use crate::turn::*;
 use bevy::prelude::*;
+use rand::Rng;
+
+/// How far the player's footsteps carry.
+pub const FOOTSTEP_NOISE: i32 = 4;
+
+/// How many turns each step of hunger lasts.
+pub const HUNGER_TURNS: i32 = 300;
+
+/// How far the player sees without a light source.
+pub const PLAYER_SIGHT: i32 = 6;
+
+/// How many items the player can carry.
+const PACK_CAPACITY: usize = 10;
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(PostStartup, add_player)
-            .add_systems(Update, move_player);
+        app.add_event::<PlayerMove>()
+            .add_event::<Noise>()
+            .add_systems(OnEnter(GameState::Playing), add_player.after(MapGenSet))
+            .add_systems(
+                Update,
+                (
+                    move_player
+                        .in_set(TurnSet::AwaitingInput)
+                        .in_set(PlayerActionSet),
+                    get_hungrier.in_set(TurnSet::PlayerTurn),
+                ),
+            );
     }
 }
 
-pub fn move_player(
-    map: ResMut<Map>,
-    keys: Res<ButtonInput<KeyCode>>,
-    mut query_player: Query<(&mut Transform, &mut Position), With<Player>>,
-    mut query_tiles: Query<(&mut Tile, &Position, &Transform), Without<Player>>,
-    mut events: EventWriter<Tick>,
-) {
-    let (mut player_transform, mut player_pos) = query_player.single_mut();
+/// Systems that carry out whatever the player asked for. Anything that asks goes before them,
+/// so the request is seen the same frame.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PlayerActionSet;
+
+/// Reads the keyboard. Left out when running headless.
+pub struct PlayerInputPlugin;
+
+impl Plugin for PlayerInputPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (player_input, improvement_input, pick_up_input)
+                .in_set(TurnSet::AwaitingInput)
+                .before(PlayerActionSet),
+        );
+    }
+}
 
+fn player_input(keys: Res<ButtonInput<KeyCode>>, mut events: EventWriter<PlayerMove>) {
+    if let Some((dx, dy)) = vi_direction(&keys) {
+        events.send(PlayerMove { dx, dy });
+    }
+}
+
+fn improvement_input(keys: Res<ButtonInput<KeyCode>>, mut events: EventWriter<ChooseImprovement>) {
+    let improvement = if keys.just_pressed(KeyCode::Digit1) {
+        Improvement::Power
+    } else if keys.just_pressed(KeyCode::Digit2) {
+        Improvement::Defense
+    } else if keys.just_pressed(KeyCode::Digit3) {
+        Improvement::Speed
+    } else {
+        return;
+    };
+    events.send(ChooseImprovement(improvement));
+}
+
+fn pick_up_input(keys: Res<ButtonInput<KeyCode>>, mut events: EventWriter<PickUp>) {
+    if keys.just_pressed(KeyCode::KeyG) {
+        events.send(PickUp);
+    }
+}
+
+/// The step asked for with the vi keys this frame, if any.
+pub fn vi_direction(keys: &ButtonInput<KeyCode>) -> Option<(i32, i32)> {
     if keys.just_pressed(KeyCode::KeyK) {
-        let top_tile = if player_pos.y < 58 {
-            map.tiles[get_tile_idx(player_pos.x, player_pos.y + 1)]
-        } else {
-            map.tiles[get_tile_idx(player_pos.x, 59)]
-        };
-        if query_tiles.get_mut(top_tile).unwrap().0.tiletype != TileType::Wall {
-            player_transform.translation.y = query_tiles.get_mut(top_tile).unwrap().2.translation.y;
-            player_pos.y = query_tiles.get_mut(top_tile).unwrap().1.y;
-        }
-        events.send(Tick);
+        Some((0, 1))
+    } else if keys.just_pressed(KeyCode::KeyJ) {
+        Some((0, -1))
+    } else if keys.just_pressed(KeyCode::KeyH) {
+        Some((-1, 0))
+    } else if keys.just_pressed(KeyCode::KeyL) {
+        Some((1, 0))
+    } else {
+        None
     }
-    if keys.just_pressed(KeyCode::KeyJ) {
-        let bot_tile = if player_pos.y > 1 {
-            map.tiles[get_tile_idx(player_pos.x, player_pos.y - 1)]
-        } else {
-            map.tiles[get_tile_idx(player_pos.x, 0)]
-        };
-        if query_tiles.get_mut(bot_tile).unwrap().0.tiletype != TileType::Wall {
-            player_transform.translation.y = query_tiles.get_mut(bot_tile).unwrap().2.translation.y;
-            player_pos.y = query_tiles.get_mut(bot_tile).unwrap().1.y;
-        }
-        events.send(Tick);
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn move_player(
+    map: ResMut<Map>,
+    mut events: EventReader<PlayerMove>,
+    mut query_player: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut Position,
+            &mut Energy,
+            Has<Asleep>,
+            Has<Confused>,
+        ),
+        With<Player>,
+    >,
+    query_tiles: Query<(&Tile, &Position, &Transform), Without<Player>>,
+    query_monsters: Query<(Entity, &Monster)>,
+    mut noises: EventWriter<Noise>,
+    mut attacks: EventWriter<Attack>,
+    mut next_state: ResMut<NextState<TurnState>>,
+    mut rng: ResMut<GameRng>,
+) {
+    let (player, mut player_transform, mut player_pos, mut energy, asleep, confused) =
+        query_player.single_mut();
+    let requested = events.read().last().copied();
+    if asleep {
+        // Sleeps through the turn whatever was pressed.
+        energy.0 -= MOVE_COST;
+        next_state.set(TurnState::PlayerTurn);
+        return;
     }
-    if keys.just_pressed(KeyCode::KeyH) {
-        let left_tile = if player_pos.x > 1 {
-            map.tiles[get_tile_idx(player_pos.x - 1, player_pos.y)]
-        } else {
-            map.tiles[get_tile_idx(0, player_pos.y)]
-        };
-
-        if query_tiles.get_mut(left_tile).unwrap().0.tiletype != TileType::Wall {
-            player_transform.translation.x =
-                query_tiles.get_mut(left_tile).unwrap().2.translation.x;
-            player_pos.x = query_tiles.get_mut(left_tile).unwrap().1.x;
-        }
-        events.send(Tick);
+    let Some(PlayerMove { mut dx, mut dy }) = requested else {
+        return;
+    };
+    if confused {
+        (dx, dy) = (rng.gen_range(-1..=1), rng.gen_range(-1..=1));
     }
-    if keys.just_pressed(KeyCode::KeyL) {
-        let right_tile = if player_pos.x < 78 {
-            map.tiles[get_tile_idx(player_pos.x + 1, player_pos.y)]
-        } else {
-            map.tiles[get_tile_idx(79, player_pos.y)]
-        };
-        if query_tiles.get_mut(right_tile).unwrap().0.tiletype != TileType::Wall {
-            player_transform.translation.x =
-                query_tiles.get_mut(right_tile).unwrap().2.translation.x;
-            player_pos.x = query_tiles.get_mut(right_tile).unwrap().1.x;
-        }
-        events.send(Tick);
+
+    let x = (player_pos.x as i32 + dx).clamp(0, map.tile_res.width as i32 - 1) as usize;
+    let y = (player_pos.y as i32 + dy).clamp(0, map.tile_res.height as i32 - 1) as usize;
+    let tile_ent = map.tiles[get_tile_idx(x, y)];
+    let (tile, tile_pos, tile_transform) = query_tiles.get(tile_ent).unwrap();
+    let monster = query_monsters
+        .iter()
+        .find(|(_, monster)| monster.occupied_tile == tile_ent);
+    if let Some((monster, _)) = monster {
+        // Walking into a monster attacks it.
+        attacks.send(Attack {
+            attacker: player,
+            target: monster,
+            kind: AttackKind::Melee,
+        });
+    } else if tile.tiletype != TileType::Wall && !tile.blocked {
+        player_transform.translation.x = tile_transform.translation.x;
+        player_transform.translation.y = tile_transform.translation.y;
+        player_pos.x = tile_pos.x;
+        player_pos.y = tile_pos.y;
+        noises.send(Noise {
+            x: player_pos.x,
+            y: player_pos.y,
+            radius: FOOTSTEP_NOISE,
+        });
     }
+    energy.0 -= MOVE_COST;
+    next_state.set(TurnState::PlayerTurn);
 }
 
 pub fn add_player(
@@ -82,24 +174,14 @@ pub fn add_player(
     map: ResMut<Map>,
 ) {
     let (player_spawn_x, player_spawn_y) = query_rooms.get(map.rooms[0]).unwrap().1.rect.center();
-    let text_style = TextStyle {
-        font: map.font.clone(),
-        font_size: map.font_size,
-        ..default()
-    };
     for (position, transform) in &mut query_tiles {
         if position.y as i32 == player_spawn_y && position.x as i32 == player_spawn_x {
             commands.spawn((
-                Text2dBundle {
-                    text: Text::from_section('@', text_style.clone())
-                        .with_justify(JustifyText::Center),
-                    transform: Transform::from_xyz(
-                        transform.translation.x,
-                        transform.translation.y,
-                        2.0,
-                    ),
-                    ..default()
+                Renderable {
+                    glyph: '@',
+                    color: Color::WHITE,
                 },
+                Transform::from_xyz(transform.translation.x, transform.translation.y, 2.0),
                 Player {},
                 Position {
                     x: position.x,
@@ -107,10 +189,63 @@ pub fn add_player(
                 },
                 Viewshed {
                     visible_tiles: Vec::new(),
-                    range: 6,
+                    range: PLAYER_SIGHT,
+                },
+                CombatStats {
+                    max_hp: 30,
+                    hp: 30,
+                    power: 5,
+                    defense: 2,
+                },
+                Hunger {
+                    state: HungerState::WellFed,
+                    turns_left: HUNGER_TURNS,
                 },
+                Experience {
+                    level: 1,
+                    xp: 0,
+                    unspent: 0,
+                },
+                Inventory {
+                    items: Vec::new(),
+                    capacity: PACK_CAPACITY,
+                },
+                Speed(NORMAL_SPEED),
+                Energy(ACTION_THRESHOLD),
                 Name::new("Player"),
             ));
         }
     }
 }
+
+fn get_hungrier(
+    mut query_player: Query<&mut Hunger, With<Player>>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+) {
+    let mut hunger = query_player.single_mut();
+    if hunger.state == HungerState::Starving {
+        return;
+    }
+    hunger.turns_left -= 1;
+    if hunger.turns_left > 0 {
+        return;
+    }
+
+    hunger.turns_left = HUNGER_TURNS;
+    hunger.state = match hunger.state {
+        HungerState::WellFed => HungerState::Normal,
+        HungerState::Normal => {
+            log.add(turn_count.0, "You are hungry", Color::srgb(1.0, 0.6, 0.0));
+            HungerState::Hungry
+        }
+        HungerState::Hungry | HungerState::Starving => {
+            log.add(
+                turn_count.0,
+                "You are starving!",
+                Color::srgb(1.0, 0.0, 0.0),
+            );
+            HungerState::Starving
+        }
+    };
+}
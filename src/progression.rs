@@ -0,0 +1,215 @@
+use crate::combat::remove_dead;
+use crate::components::*;
+use crate::events::*;
+use crate::gamelog::GameLog;
+use crate::player::PlayerActionSet;
+use crate::resources::*;
+use crate::turn::*;
+use bevy::prelude::*;
+
+/// Xp needed for each level, times the level the player is on.
+const XP_PER_LEVEL: i32 = 30;
+
+/// Max hp gained with every level.
+const HP_PER_LEVEL: i32 = 5;
+
+/// Speed gained by picking `Improvement::Speed`.
+const SPEED_PER_IMPROVEMENT: i32 = 2;
+
+/// Hands out xp, levels the player up and applies the improvements they pick.
+pub struct ProgressionPlugin;
+
+impl Plugin for ProgressionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<GainXp>()
+            .add_event::<ChooseImprovement>()
+            .add_systems(
+                Update,
+                (
+                    gain_xp
+                        .after(remove_dead)
+                        .before(TurnSet::EndTurn)
+                        .run_if(in_state(GameState::Playing)),
+                    improve
+                        .in_set(TurnSet::AwaitingInput)
+                        .in_set(PlayerActionSet),
+                ),
+            );
+    }
+}
+
+/// Xp it takes to get from `level` to the one after.
+pub fn xp_to_next_level(level: i32) -> i32 {
+    level * XP_PER_LEVEL
+}
+
+fn gain_xp(
+    mut events: EventReader<GainXp>,
+    mut query_players: Query<(&mut Experience, &mut CombatStats)>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+) {
+    for event in events.read() {
+        let Ok((mut experience, mut stats)) = query_players.get_mut(event.target) else {
+            continue;
+        };
+        experience.xp += event.amount;
+        while experience.xp >= xp_to_next_level(experience.level) {
+            experience.xp -= xp_to_next_level(experience.level);
+            experience.level += 1;
+            experience.unspent += 1;
+            stats.max_hp += HP_PER_LEVEL;
+            stats.hp += HP_PER_LEVEL;
+            log.add(
+                turn_count.0,
+                format!("Welcome to level {}!", experience.level),
+                Color::srgb(1.0, 0.85, 0.0),
+            );
+            log.add(
+                turn_count.0,
+                "Pick an improvement: 1) Power  2) Defense  3) Speed",
+                Color::srgb(1.0, 0.85, 0.0),
+            );
+        }
+    }
+}
+
+/// Picking doesn't take a turn.
+fn improve(
+    mut events: EventReader<ChooseImprovement>,
+    mut query_player: Query<(&mut Experience, &mut CombatStats, &mut Speed), With<Player>>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+) {
+    let Ok((mut experience, mut stats, mut speed)) = query_player.get_single_mut() else {
+        return;
+    };
+    for ChooseImprovement(improvement) in events.read() {
+        if experience.unspent == 0 {
+            continue;
+        }
+        experience.unspent -= 1;
+        let message = match improvement {
+            Improvement::Power => {
+                stats.power += 1;
+                "You feel stronger"
+            }
+            Improvement::Defense => {
+                stats.defense += 1;
+                "You feel tougher"
+            }
+            Improvement::Speed => {
+                speed.0 += SPEED_PER_IMPROVEMENT;
+                "You feel quicker"
+            }
+        };
+        log.add(turn_count.0, message, Color::srgb(1.0, 0.85, 0.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::schedule::Schedule;
+
+    fn new_player() -> (World, Entity) {
+        let mut world = World::new();
+        world.init_resource::<Events<GainXp>>();
+        world.init_resource::<Events<ChooseImprovement>>();
+        world.init_resource::<TurnCount>();
+        world.init_resource::<GameLog>();
+        let player = world
+            .spawn((
+                Player,
+                Experience {
+                    level: 1,
+                    xp: 0,
+                    unspent: 0,
+                },
+                CombatStats {
+                    max_hp: 30,
+                    hp: 20,
+                    power: 5,
+                    defense: 2,
+                },
+                Speed(10),
+            ))
+            .id();
+        (world, player)
+    }
+
+    /// Runs both systems once. Events are cleared first, since a fresh schedule would read
+    /// old ones again.
+    fn run(world: &mut World) {
+        let mut schedule = Schedule::default();
+        schedule.add_systems((gain_xp, improve).chain());
+        schedule.run(world);
+        world.resource_mut::<Events<GainXp>>().clear();
+        world.resource_mut::<Events<ChooseImprovement>>().clear();
+    }
+
+    fn gain(world: &mut World, target: Entity, amount: i32) {
+        world.send_event(GainXp { target, amount });
+        run(world);
+    }
+
+    fn experience(world: &World, player: Entity) -> (i32, i32, i32) {
+        let experience = world.get::<Experience>(player).unwrap();
+        (experience.level, experience.xp, experience.unspent)
+    }
+
+    #[test]
+    fn levels_take_more_xp_each_time() {
+        let (mut world, player) = new_player();
+        gain(&mut world, player, XP_PER_LEVEL - 1);
+        assert_eq!(experience(&world, player), (1, XP_PER_LEVEL - 1, 0));
+
+        gain(&mut world, player, 1);
+        assert_eq!(experience(&world, player), (2, 0, 1));
+        let stats = world.get::<CombatStats>(player).unwrap();
+        assert_eq!((stats.hp, stats.max_hp), (25, 35));
+
+        gain(&mut world, player, XP_PER_LEVEL);
+        assert_eq!(experience(&world, player), (2, XP_PER_LEVEL, 1));
+    }
+
+    #[test]
+    fn leftover_xp_carries_over() {
+        let (mut world, player) = new_player();
+        // 30 for level 2, 60 for level 3, with 5 to spare.
+        gain(&mut world, player, 95);
+        assert_eq!(experience(&world, player), (3, 5, 2));
+        assert_eq!(world.get::<CombatStats>(player).unwrap().max_hp, 40);
+    }
+
+    #[test]
+    fn improvements_need_a_level_up_to_spend() {
+        let (mut world, player) = new_player();
+        world.send_event(ChooseImprovement(Improvement::Power));
+        run(&mut world);
+        assert_eq!(world.get::<CombatStats>(player).unwrap().power, 5);
+
+        gain(&mut world, player, 3 * XP_PER_LEVEL);
+        assert_eq!(experience(&world, player).2, 2);
+        world.send_event(ChooseImprovement(Improvement::Power));
+        world.send_event(ChooseImprovement(Improvement::Defense));
+        world.send_event(ChooseImprovement(Improvement::Speed));
+        run(&mut world);
+        let stats = world.get::<CombatStats>(player).unwrap();
+        assert_eq!((stats.power, stats.defense), (6, 3));
+        assert_eq!(
+            world.get::<Speed>(player).unwrap().0,
+            10,
+            "nothing left to spend"
+        );
+        assert_eq!(experience(&world, player).2, 0);
+
+        gain(&mut world, player, 3 * XP_PER_LEVEL);
+        world.send_event(ChooseImprovement(Improvement::Speed));
+        run(&mut world);
+        assert_eq!(
+            world.get::<Speed>(player).unwrap().0,
+            10 + SPEED_PER_IMPROVEMENT
+        );
+    }
+}
@@ -1 +1,26 @@
-use rand::{Rng, SeedableRng};
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+
+/// The one random number generator the game rolls with, so a run can be replayed from its seed.
+#[derive(Resource, Deref, DerefMut)]
+pub struct GameRng {
+    pub seed: u64,
+    #[deref]
+    rng: XorShiftRng,
+}
+
+impl GameRng {
+    pub fn from_seed(seed: u64) -> Self {
+        GameRng {
+            seed,
+            rng: XorShiftRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng::from_seed(rand::random())
+    }
+}
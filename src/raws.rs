@@ -0,0 +1,220 @@
+use crate::components::AiState;
+use crate::consumables::ItemEffect;
+use crate::effects::EffectKind;
+use crate::targeting::Aim;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+pub const MONSTER_RAWS: RawsFile = RawsFile {
+    path: "assets/raws/monsters.ron",
+    contents: include_str!("../assets/raws/monsters.ron"),
+};
+pub const ITEM_RAWS: RawsFile = RawsFile {
+    path: "assets/raws/items.ron",
+    contents: include_str!("../assets/raws/items.ron"),
+};
+
+/// A raws file, built into the binary so the game runs from any directory.
+#[derive(Clone, Copy)]
+pub struct RawsFile {
+    pub path: &'static str,
+    pub contents: &'static str,
+}
+
+/// Monster templates from `assets/raws/monsters.ron`, built into the game.
+#[derive(Resource, Deserialize)]
+pub struct MonsterRaws {
+    pub monsters: Vec<MonsterTemplate>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct MonsterTemplate {
+    pub name: String,
+    pub glyph: char,
+    pub color: (f32, f32, f32),
+    pub stats: MonsterStats,
+    /// Awarded for the kill.
+    pub xp: i32,
+    pub vision: i32,
+    pub ai: AiBehaviour,
+    pub idle: IdleBehaviour,
+    /// Shallowest and deepest dungeon level the monster turns up on.
+    pub depth: (i32, i32),
+    #[serde(default)]
+    pub inflicts: Option<Inflicts>,
+}
+
+/// Item templates from `assets/raws/items.ron`, built into the game.
+#[derive(Resource, Deserialize)]
+pub struct ItemRaws {
+    pub items: Vec<ItemTemplate>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ItemTemplate {
+    pub name: String,
+    pub glyph: char,
+    pub color: (f32, f32, f32),
+    /// Shallowest and deepest dungeon level the item turns up on.
+    pub depth: (i32, i32),
+    #[serde(default)]
+    pub consumable: Option<Consumable>,
+    #[serde(default)]
+    pub equipment: Option<Equipment>,
+}
+
+/// An item that is used up, doing everything in `effects` to whatever it lands on. Items
+/// without an `aim` are used on whoever uses them.
+#[derive(Component, Deserialize, Clone)]
+pub struct Consumable {
+    pub effects: Vec<ItemEffect>,
+    #[serde(default)]
+    pub aim: Option<Aim>,
+}
+
+/// An item that can be worn in `slot`. Its bonuses count for as long as it's worn.
+#[derive(Component, Deserialize, Clone, Copy)]
+pub struct Equipment {
+    pub slot: EquipmentSlot,
+    #[serde(default)]
+    pub power: i32,
+    #[serde(default)]
+    pub defense: i32,
+    /// How far the wearer sees by it, for light sources.
+    #[serde(default)]
+    pub light: Option<i32>,
+}
+
+/// Only one item can be worn in each.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EquipmentSlot {
+    MainHand,
+    OffHand,
+    Head,
+    Body,
+    Feet,
+    Ring,
+    Light,
+}
+
+/// A status effect the monster's attacks may put on whatever they hit.
+#[derive(Component, Deserialize, Clone, Copy)]
+pub struct Inflicts {
+    pub effect: EffectKind,
+    pub turns: i32,
+    /// Out of 100.
+    pub chance: i32,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct MonsterStats {
+    pub speed: i32,
+    pub morale: i32,
+    pub hp: i32,
+    pub power: i32,
+    pub defense: i32,
+}
+
+#[derive(Component, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AiBehaviour {
+    /// Walks up to the player.
+    Melee,
+    /// Shoots from up to `range` tiles away, backing off when the player gets within
+    /// `keep_away`. Arrows stop at whatever is in the way.
+    Archer { range: i32, keep_away: i32 },
+    /// Like an archer, but spells go over other monsters' heads, so casters are happy to
+    /// hang back behind them.
+    Caster { range: i32, keep_away: i32 },
+}
+
+/// What a monster does while it has no idea where the player is.
+#[derive(Component, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdleBehaviour {
+    Sleep,
+    Guard,
+    Wander,
+}
+
+impl IdleBehaviour {
+    pub fn state(self) -> AiState {
+        match self {
+            IdleBehaviour::Sleep => AiState::Sleeping,
+            IdleBehaviour::Guard => AiState::Idle,
+            IdleBehaviour::Wander => AiState::Wandering { destination: None },
+        }
+    }
+
+    /// Where a hunt that lost the player ends up.
+    pub fn lost_player_state(self) -> AiState {
+        match self {
+            IdleBehaviour::Wander => AiState::Wandering { destination: None },
+            IdleBehaviour::Sleep | IdleBehaviour::Guard => AiState::ReturningToPost,
+        }
+    }
+}
+
+impl MonsterTemplate {
+    pub fn color(&self) -> Color {
+        let (red, green, blue) = self.color;
+        Color::srgb(red, green, blue)
+    }
+
+    pub fn spawns_at(&self, depth: i32) -> bool {
+        (self.depth.0..=self.depth.1).contains(&depth)
+    }
+}
+
+impl MonsterRaws {
+    pub fn template(&self, name: &str) -> Option<&MonsterTemplate> {
+        self.monsters.iter().find(|template| template.name == name)
+    }
+}
+
+impl FromWorld for MonsterRaws {
+    fn from_world(_world: &mut World) -> Self {
+        let raws: MonsterRaws = load_raws(MONSTER_RAWS);
+        // Energy is handed out until someone can act, so a monster that never gains any
+        // would stall every turn.
+        for template in raws.monsters.iter() {
+            assert!(
+                template.stats.speed > 0,
+                "{}: the {} needs a speed above 0",
+                MONSTER_RAWS.path,
+                template.name
+            );
+        }
+        raws
+    }
+}
+
+impl ItemTemplate {
+    pub fn color(&self) -> Color {
+        let (red, green, blue) = self.color;
+        Color::srgb(red, green, blue)
+    }
+
+    pub fn spawns_at(&self, depth: i32) -> bool {
+        (self.depth.0..=self.depth.1).contains(&depth)
+    }
+}
+
+impl ItemRaws {
+    pub fn template(&self, name: &str) -> Option<&ItemTemplate> {
+        self.items.iter().find(|template| template.name == name)
+    }
+}
+
+impl FromWorld for ItemRaws {
+    fn from_world(_world: &mut World) -> Self {
+        load_raws(ITEM_RAWS)
+    }
+}
+
+/// The game can't run without its raws, so a file that doesn't parse ends it with the line
+/// and column at fault.
+pub fn load_raws<T: for<'de> Deserialize<'de>>(raws: RawsFile) -> T {
+    ron::from_str(raws.contents).unwrap_or_else(|err| {
+        eprintln!("error in {}:{err}", raws.path);
+        std::process::exit(1);
+    })
+}
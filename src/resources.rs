@@ -1,11 +1,23 @@
 use bevy::prelude::*;
 
+pub const MAP_WIDTH: usize = 80;
+/// Leaves the bottom of the window for the HUD and message log.
+pub const MAP_HEIGHT: usize = 48;
+pub const TILE_SIZE: f32 = 10.0;
+
+/// Lines of the message log shown under the map.
+pub const LOG_LINES: usize = 6;
+pub const LOG_PANEL_HEIGHT: f32 = LOG_LINES as f32 * TILE_SIZE * 1.5;
+pub const HUD_HEIGHT: f32 = TILE_SIZE * 3.0;
+/// Everything under the map.
+pub const UI_HEIGHT: f32 = HUD_HEIGHT + LOG_PANEL_HEIGHT;
+
 #[derive(Resource)]
 pub struct Map {
     pub tiles: Vec<Entity>,
     pub rooms: Vec<Entity>,
-    pub font_size: f32,
-    pub font: Handle<Font>,
+    pub depth: i32,
+    pub tile_size: f32,
     pub tile_res: TileResolution,
 }
 
@@ -15,24 +27,39 @@ pub struct TileResolution {
     pub width: usize,
 }
 
-impl FromWorld for Map {
-    fn from_world(world: &mut World) -> Self {
-        let font = world.load_asset("fonts/Mx437_IBM_BIOS.ttf");
-        let font_size = 10.0;
-        let mut window_query = world.query::<&Window>();
-        let window = window_query.single(world);
-        let map_width = window.resolution.width() / font_size;
-        let map_height = window.resolution.height() / font_size;
-
+impl Default for Map {
+    fn default() -> Self {
         Map {
             tiles: Vec::new(),
             rooms: Vec::new(),
-            font_size,
-            font: font.clone(),
+            depth: 1,
+            tile_size: TILE_SIZE,
             tile_res: TileResolution {
-                height: map_height as usize,
-                width: map_width as usize,
+                height: MAP_HEIGHT,
+                width: MAP_WIDTH,
             },
         }
     }
 }
+
+/// Monsters that get to act this round, in the order they act.
+#[derive(Resource, Default)]
+pub struct TurnOrder {
+    pub acting: Vec<Entity>,
+}
+
+/// Goes up every time tile types are written. Anything worked out from the layout alone
+/// rebuilds when it changes.
+#[derive(Resource, Default)]
+pub struct TerrainRevision(pub u32);
+
+/// How many turns the player has taken.
+#[derive(Resource, Default)]
+pub struct TurnCount(pub u32);
+
+/// How the last run ended, for the game-over screen.
+#[derive(Resource, Default)]
+pub struct Death {
+    pub cause: String,
+    pub turns: u32,
+}
@@ -0,0 +1,299 @@
+use crate::random::GameRng;
+use crate::raws::*;
+use crate::rect::Rect;
+use bevy::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+
+pub const SPAWN_TABLE_RAWS: RawsFile = RawsFile {
+    path: "assets/raws/spawn_table.ron",
+    contents: include_str!("../assets/raws/spawn_table.ron"),
+};
+
+/// What turns up in a room and how often, read from `assets/raws/spawn_table.ron`.
+#[derive(Resource, Deserialize)]
+pub struct SpawnTable {
+    pub per_room: SpawnCount,
+    pub monsters: Vec<SpawnEntry>,
+    pub items_per_room: SpawnCount,
+    pub items: Vec<SpawnEntry>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+pub struct SpawnCount {
+    pub dice: i32,
+    pub sides: i32,
+    pub bonus: i32,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SpawnEntry {
+    pub name: String,
+    pub weight: i32,
+    pub depth_bonus: i32,
+    /// Smallest and largest group the monster turns up in.
+    #[serde(default = "alone")]
+    pub pack_size: (usize, usize),
+}
+
+fn alone() -> (usize, usize) {
+    (1, 1)
+}
+
+impl FromWorld for SpawnTable {
+    fn from_world(_world: &mut World) -> Self {
+        load_raws(SPAWN_TABLE_RAWS)
+    }
+}
+
+impl SpawnEntry {
+    pub fn weight_at(&self, depth: i32) -> i32 {
+        (self.weight + self.depth_bonus * (depth - 1)).max(0)
+    }
+}
+
+impl SpawnCount {
+    pub fn roll(&self, depth: i32, rng: &mut GameRng) -> usize {
+        let rolled: i32 = (0..self.dice).map(|_| rng.gen_range(1..=self.sides)).sum();
+        (rolled + self.bonus + depth - 1).max(0) as usize
+    }
+}
+
+/// Picks one entry by weight, among the entries `allowed` lets through.
+pub fn roll_entry<'a>(
+    entries: &'a [SpawnEntry],
+    depth: i32,
+    rng: &mut GameRng,
+    allowed: impl Fn(&SpawnEntry) -> bool,
+) -> Option<&'a SpawnEntry> {
+    let candidates: Vec<&SpawnEntry> = entries
+        .iter()
+        .filter(|entry| allowed(entry) && entry.weight_at(depth) > 0)
+        .collect();
+    let total: i32 = candidates.iter().map(|entry| entry.weight_at(depth)).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut roll = rng.gen_range(0..total);
+    for entry in candidates {
+        roll -= entry.weight_at(depth);
+        if roll < 0 {
+            return Some(entry);
+        }
+    }
+    None
+}
+
+/// Which monsters go where in `room`, in the groups they were rolled in. Every monster gets
+/// its own floor tile, and the same seed always gives the same spawns.
+pub fn roll_room_spawns<'a>(
+    table: &SpawnTable,
+    raws: &'a MonsterRaws,
+    depth: i32,
+    room: &Rect,
+    rng: &mut GameRng,
+) -> Vec<Vec<(&'a MonsterTemplate, (usize, usize))>> {
+    let mut free_tiles = Vec::new();
+    for y in room.y0..=room.y1 {
+        for x in room.x0..=room.x1 {
+            free_tiles.push((x as usize, y as usize));
+        }
+    }
+
+    let mut spawns = Vec::new();
+    for _ in 0..table.per_room.roll(depth, rng) {
+        if free_tiles.is_empty() {
+            break;
+        }
+        let Some(entry) = roll_entry(&table.monsters, depth, rng, |entry| {
+            raws.template(&entry.name)
+                .is_some_and(|template| template.spawns_at(depth))
+        }) else {
+            break;
+        };
+        let template = raws.template(&entry.name).unwrap();
+        let (min_size, max_size) = entry.pack_size;
+        let size = rng.gen_range(min_size..=max_size).min(free_tiles.len());
+        let group = (0..size)
+            .map(|_| {
+                let tile = free_tiles.swap_remove(rng.gen_range(0..free_tiles.len()));
+                (template, tile)
+            })
+            .collect();
+        spawns.push(group);
+    }
+    spawns
+}
+
+/// Which items go where in `room`. Items don't block, so they can share a tile with a
+/// monster, but never with each other.
+pub fn roll_room_items<'a>(
+    table: &SpawnTable,
+    raws: &'a ItemRaws,
+    depth: i32,
+    room: &Rect,
+    rng: &mut GameRng,
+) -> Vec<(&'a ItemTemplate, (usize, usize))> {
+    let mut free_tiles = Vec::new();
+    for y in room.y0..=room.y1 {
+        for x in room.x0..=room.x1 {
+            free_tiles.push((x as usize, y as usize));
+        }
+    }
+
+    let mut spawns = Vec::new();
+    for _ in 0..table.items_per_room.roll(depth, rng) {
+        if free_tiles.is_empty() {
+            break;
+        }
+        let Some(entry) = roll_entry(&table.items, depth, rng, |entry| {
+            raws.template(&entry.name)
+                .is_some_and(|template| template.spawns_at(depth))
+        }) else {
+            break;
+        };
+        let tile = free_tiles.swap_remove(rng.gen_range(0..free_tiles.len()));
+        spawns.push((raws.template(&entry.name).unwrap(), tile));
+    }
+    spawns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn entry(name: &str, weight: i32, depth_bonus: i32) -> SpawnEntry {
+        SpawnEntry {
+            name: name.to_string(),
+            weight,
+            depth_bonus,
+            pack_size: (1, 1),
+        }
+    }
+
+    /// The real monster raws, with a crowd rolled into every room.
+    fn crowded() -> (SpawnTable, MonsterRaws) {
+        let mut table: SpawnTable = load_raws(SPAWN_TABLE_RAWS);
+        table.per_room = SpawnCount {
+            dice: 2,
+            sides: 6,
+            bonus: 6,
+        };
+        (table, load_raws(MONSTER_RAWS))
+    }
+
+    fn spawned<'a>(
+        spawns: &[Vec<(&'a MonsterTemplate, (usize, usize))>],
+    ) -> Vec<(&'a str, (usize, usize))> {
+        spawns
+            .iter()
+            .flatten()
+            .map(|(template, tile)| (template.name.as_str(), *tile))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_spawns() {
+        let (table, raws) = crowded();
+        let room = Rect::new(10, 10, 16, 14);
+        for seed in 0..20 {
+            let first = roll_room_spawns(&table, &raws, 3, &room, &mut GameRng::from_seed(seed));
+            let second = roll_room_spawns(&table, &raws, 3, &room, &mut GameRng::from_seed(seed));
+            assert_eq!(spawned(&first), spawned(&second), "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn spawns_never_share_a_tile() {
+        let (table, raws) = crowded();
+        let room = Rect::new(2, 3, 4, 5);
+        for seed in 0..50 {
+            let spawns = roll_room_spawns(&table, &raws, 4, &room, &mut GameRng::from_seed(seed));
+            let tiles: Vec<(usize, usize)> =
+                spawned(&spawns).iter().map(|&(_, tile)| tile).collect();
+            let unique: HashSet<_> = tiles.iter().collect();
+            assert_eq!(unique.len(), tiles.len(), "seed {seed}: {tiles:?}");
+            assert!(tiles.len() <= 9);
+            assert!(tiles
+                .iter()
+                .all(|&(x, y)| (2..=4).contains(&x) && (3..=5).contains(&y)));
+        }
+    }
+
+    #[test]
+    fn monsters_only_spawn_within_their_depths() {
+        let (table, raws) = crowded();
+        let room = Rect::new(0, 0, 9, 9);
+        for depth in [1, 7, 9] {
+            for seed in 0..30 {
+                let spawns =
+                    roll_room_spawns(&table, &raws, depth, &room, &mut GameRng::from_seed(seed));
+                for (name, _) in spawned(&spawns) {
+                    let template = raws.template(name).unwrap();
+                    assert!(template.spawns_at(depth), "{name} at depth {depth}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn depth_bonus_adds_weight_per_level() {
+        let growing = entry("Ogre", 2, 3);
+        assert_eq!(growing.weight_at(1), 2);
+        assert_eq!(growing.weight_at(3), 8);
+
+        let fading = entry("Goblin", 5, -2);
+        assert_eq!(fading.weight_at(3), 1);
+        assert_eq!(fading.weight_at(4), 0, "weights never go below zero");
+        assert_eq!(fading.weight_at(10), 0);
+    }
+
+    #[test]
+    fn rolls_follow_the_weights() {
+        let entries = [entry("Goblin", 5, -2), entry("Ogre", 1, 0)];
+        let mut rng = GameRng::from_seed(5);
+        for _ in 0..100 {
+            let rolled = roll_entry(&entries, 4, &mut rng, |_| true).unwrap();
+            assert_eq!(rolled.name, "Ogre", "zero weight entries are never rolled");
+        }
+
+        let entries = [entry("Goblin", 1, 0), entry("Ogre", 1, 4)];
+        let ogres = (0..1000)
+            .filter(|_| roll_entry(&entries, 3, &mut rng, |_| true).unwrap().name == "Ogre")
+            .count();
+        // 9 in 10 at depth 3.
+        assert!((850..=950).contains(&ogres), "{ogres}");
+    }
+
+    #[test]
+    fn nothing_is_rolled_when_nothing_is_allowed() {
+        let entries = [entry("Goblin", 3, 0)];
+        let mut rng = GameRng::from_seed(1);
+        assert!(roll_entry(&entries, 1, &mut rng, |_| false).is_none());
+        assert!(roll_entry(&[entry("Goblin", 0, 0)], 1, &mut rng, |_| true).is_none());
+    }
+
+    #[test]
+    fn items_never_share_a_tile() {
+        let mut table: SpawnTable = load_raws(SPAWN_TABLE_RAWS);
+        table.items_per_room = SpawnCount {
+            dice: 2,
+            sides: 6,
+            bonus: 4,
+        };
+        let raws: ItemRaws = load_raws(ITEM_RAWS);
+        let room = Rect::new(5, 5, 7, 7);
+        for seed in 0..50 {
+            let items = roll_room_items(&table, &raws, 2, &room, &mut GameRng::from_seed(seed));
+            let tiles: Vec<(usize, usize)> = items.iter().map(|&(_, tile)| tile).collect();
+            let unique: HashSet<_> = tiles.iter().collect();
+            assert_eq!(unique.len(), tiles.len(), "seed {seed}: {tiles:?}");
+            assert!(!tiles.is_empty() && tiles.len() <= 9);
+            assert!(tiles
+                .iter()
+                .all(|&(x, y)| (5..=7).contains(&x) && (5..=7).contains(&y)));
+        }
+    }
+}
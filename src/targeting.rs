@@ -0,0 +1,372 @@
+use crate::aoe::*;
+use crate::components::*;
+use crate::effects::Asleep;
+use crate::events::*;
+use crate::gamelog::GameLog;
+use crate::initiative::MOVE_COST;
+use crate::los::line_of_fire;
+use crate::map::get_tile_idx;
+use crate::monsters::distance;
+use crate::player::{vi_direction, PlayerActionSet};
+use crate::resources::*;
+use crate::turn::*;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+/// The player's bow, fired with F.
+pub const BOW: Aim = Aim {
+    range: 6,
+    area: None,
+};
+
+/// A small burst of magic, cast with Z.
+pub const SPELL: Aim = Aim {
+    range: 5,
+    area: Some(AreaOfEffect::Ball { radius: 1 }),
+};
+
+/// Picking a target for a ranged attack or an aimed item. Tab cycles through the monsters in
+/// view, the vi keys move the cursor a tile at a time, Enter fires and Escape puts the bow
+/// away. Only added when the game has a window.
+pub struct TargetingPlugin;
+
+impl Plugin for TargetingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                start_targeting
+                    .in_set(TurnSet::AwaitingInput)
+                    .before(PlayerActionSet),
+                (move_cursor, confirm_target, draw_preview)
+                    .chain()
+                    .in_set(TurnSet::Targeting),
+            ),
+        )
+        .add_systems(OnEnter(TurnState::Targeting), explain_targeting)
+        .add_systems(OnExit(TurnState::Targeting), stop_targeting);
+    }
+}
+
+/// What is being aimed: how far it reaches and what it covers when it lands.
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct Aim {
+    pub range: usize,
+    /// `None` hits whatever the shot reaches first.
+    #[serde(default)]
+    pub area: Option<AreaOfEffect>,
+}
+
+/// What the shot does to whatever it hits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TargetAction {
+    Attack(AttackKind),
+    UseItem(Entity),
+}
+
+/// Only exists while the player is aiming.
+#[derive(Resource)]
+pub struct Targeting {
+    pub aim: Aim,
+    pub action: TargetAction,
+    pub cursor: (usize, usize),
+}
+
+impl Targeting {
+    /// Starts with the cursor on the nearest monster in view, or on `origin` if there's none.
+    pub fn at_nearest(
+        aim: Aim,
+        action: TargetAction,
+        origin: (usize, usize),
+        viewshed: &Viewshed,
+        query_monsters: &Query<(&Monster, &Position)>,
+    ) -> Self {
+        let cursor = visible_targets(origin, viewshed, query_monsters)
+            .first()
+            .copied()
+            .unwrap_or(origin);
+        Targeting {
+            aim,
+            action,
+            cursor,
+        }
+    }
+}
+
+#[derive(Component)]
+struct TargetMarker;
+
+/// Where a shot at the cursor would fly, and the tiles it would hit.
+struct Shot {
+    path: Vec<(usize, usize)>,
+    hits: Vec<(usize, usize)>,
+    in_range: bool,
+}
+
+fn start_targeting(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    query_player: Query<(&Position, &Viewshed, Has<Asleep>), With<Player>>,
+    query_monsters: Query<(&Monster, &Position)>,
+    mut next_state: ResMut<NextState<TurnState>>,
+) {
+    let (aim, kind) = if keys.just_pressed(KeyCode::KeyF) {
+        (BOW, AttackKind::Arrow)
+    } else if keys.just_pressed(KeyCode::KeyZ) {
+        (SPELL, AttackKind::Spell)
+    } else {
+        return;
+    };
+    let Ok((player_pos, viewshed, asleep)) = query_player.get_single() else {
+        return;
+    };
+    if asleep {
+        return;
+    }
+    commands.insert_resource(Targeting::at_nearest(
+        aim,
+        TargetAction::Attack(kind),
+        (player_pos.x, player_pos.y),
+        viewshed,
+        &query_monsters,
+    ));
+    next_state.set(TurnState::Targeting);
+}
+
+fn explain_targeting(turn_count: Res<TurnCount>, mut log: ResMut<GameLog>) {
+    log.add(
+        turn_count.0,
+        "Tab or hjkl to aim, Enter to fire, Esc to cancel",
+        Color::srgb(0.5, 0.5, 0.5),
+    );
+}
+
+fn move_cursor(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut targeting: ResMut<Targeting>,
+    query_player: Query<(&Position, &Viewshed), With<Player>>,
+    query_monsters: Query<(&Monster, &Position)>,
+    map: Res<Map>,
+) {
+    let (player_pos, viewshed) = query_player.single();
+    if keys.just_pressed(KeyCode::Tab) {
+        let targets = visible_targets((player_pos.x, player_pos.y), viewshed, &query_monsters);
+        if targets.is_empty() {
+            return;
+        }
+        let next = targets
+            .iter()
+            .position(|&tile| tile == targeting.cursor)
+            .map_or(0, |idx| (idx + 1) % targets.len());
+        targeting.cursor = targets[next];
+    } else if let Some((dx, dy)) = vi_direction(&keys) {
+        let (x, y) = targeting.cursor;
+        targeting.cursor = (
+            (x as i32 + dx).clamp(0, map.tile_res.width as i32 - 1) as usize,
+            (y as i32 + dy).clamp(0, map.tile_res.height as i32 - 1) as usize,
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn confirm_target(
+    keys: Res<ButtonInput<KeyCode>>,
+    targeting: Res<Targeting>,
+    mut query_player: Query<(Entity, &Position, &Viewshed, &mut Energy), With<Player>>,
+    query_monsters: Query<(Entity, &Monster)>,
+    query_tiles: Query<&Tile>,
+    map: Res<Map>,
+    mut attacks: EventWriter<Attack>,
+    mut uses: EventWriter<UseItem>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+    mut next_state: ResMut<NextState<TurnState>>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        next_state.set(TurnState::AwaitingInput);
+        return;
+    }
+    if !keys.just_pressed(KeyCode::Enter) {
+        return;
+    }
+
+    let (player, player_pos, viewshed, mut energy) = query_player.single_mut();
+    let shot = plan_shot(
+        &map,
+        &query_tiles,
+        (player_pos.x, player_pos.y),
+        viewshed,
+        &targeting,
+    );
+    if !shot.in_range {
+        log.add(
+            turn_count.0,
+            "You can't aim there",
+            Color::srgb(0.5, 0.5, 0.5),
+        );
+        return;
+    }
+
+    let kind = match targeting.action {
+        TargetAction::Attack(kind) => kind,
+        TargetAction::UseItem(item) => {
+            // Used once input is back, like any other item.
+            uses.send(UseItem {
+                item,
+                target: Some(targeting.cursor),
+            });
+            next_state.set(TurnState::AwaitingInput);
+            return;
+        }
+    };
+    let hit_tiles = tile_entities(&map, &shot.hits);
+    let mut hit_anything = false;
+    for (monster, _) in query_monsters
+        .iter()
+        .filter(|(_, monster)| hit_tiles.contains(&monster.occupied_tile))
+    {
+        attacks.send(Attack {
+            attacker: player,
+            target: monster,
+            kind,
+        });
+        hit_anything = true;
+    }
+    if !hit_anything {
+        log.add(turn_count.0, "You hit nothing", Color::srgb(0.5, 0.5, 0.5));
+    }
+    energy.0 -= MOVE_COST;
+    next_state.set(TurnState::PlayerTurn);
+}
+
+/// Shades the tiles the shot would cross, and what it would hit, in red if it can't be fired.
+fn draw_preview(
+    mut commands: Commands,
+    targeting: Res<Targeting>,
+    query_player: Query<(&Position, &Viewshed), With<Player>>,
+    query_tiles: Query<&Tile>,
+    query_transforms: Query<&Transform, With<Tile>>,
+    query_markers: Query<Entity, With<TargetMarker>>,
+    map: Res<Map>,
+) {
+    if !targeting.is_changed() {
+        return;
+    }
+    for marker in query_markers.iter() {
+        commands.entity(marker).despawn();
+    }
+    let (player_pos, viewshed) = query_player.single();
+    let shot = plan_shot(
+        &map,
+        &query_tiles,
+        (player_pos.x, player_pos.y),
+        viewshed,
+        &targeting,
+    );
+
+    let (path_color, hit_color) = if shot.in_range {
+        (
+            Color::srgba(1.0, 1.0, 0.0, 0.3),
+            Color::srgba(1.0, 0.5, 0.0, 0.5),
+        )
+    } else {
+        (
+            Color::srgba(1.0, 0.0, 0.0, 0.3),
+            Color::srgba(1.0, 0.0, 0.0, 0.5),
+        )
+    };
+    let marked = shot
+        .path
+        .iter()
+        .filter(|tile| !shot.hits.contains(tile))
+        .map(|&tile| (tile, path_color))
+        .chain(shot.hits.iter().map(|&tile| (tile, hit_color)))
+        // Keeps the cursor visible when the shot stops short of it.
+        .chain(
+            std::iter::once(targeting.cursor)
+                .filter(|tile| !shot.path.contains(tile) && !shot.hits.contains(tile))
+                .map(|tile| (tile, path_color)),
+        );
+    for ((x, y), color) in marked {
+        let Ok(tile_transform) = query_transforms.get(map.tiles[get_tile_idx(x, y)]) else {
+            continue;
+        };
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color,
+                    custom_size: Some(Vec2::splat(TILE_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(
+                    tile_transform.translation.x,
+                    tile_transform.translation.y,
+                    1.0,
+                ),
+                ..default()
+            },
+            TargetMarker,
+        ));
+    }
+}
+
+fn stop_targeting(mut commands: Commands, query_markers: Query<Entity, With<TargetMarker>>) {
+    for marker in query_markers.iter() {
+        commands.entity(marker).despawn();
+    }
+    commands.remove_resource::<Targeting>();
+}
+
+/// Tiles of the monsters the player can see, nearest first.
+fn visible_targets(
+    origin: (usize, usize),
+    viewshed: &Viewshed,
+    query_monsters: &Query<(&Monster, &Position)>,
+) -> Vec<(usize, usize)> {
+    let mut targets: Vec<(usize, usize)> = query_monsters
+        .iter()
+        .filter(|(monster, _)| viewshed.visible_tiles.contains(&monster.occupied_tile))
+        .map(|(_, position)| (position.x, position.y))
+        .collect();
+    targets.sort_by_key(|&tile| (distance(origin, tile), tile));
+    targets
+}
+
+fn plan_shot(
+    map: &Map,
+    query_tiles: &Query<&Tile>,
+    origin: (usize, usize),
+    viewshed: &Viewshed,
+    targeting: &Targeting,
+) -> Shot {
+    let target = targeting.cursor;
+    let in_range = target != origin
+        && distance(origin, target) <= targeting.aim.range
+        && viewshed
+            .visible_tiles
+            .contains(&map.tiles[get_tile_idx(target.0, target.1)]);
+    Shot {
+        path: line_of_fire(map, query_tiles, origin, target).path,
+        hits: tiles_hit(map, query_tiles, origin, target, targeting.aim),
+        in_range,
+    }
+}
+
+/// Tiles hit by `aim` fired from `origin` at `target`.
+pub fn tiles_hit(
+    map: &Map,
+    query_tiles: &Query<&Tile>,
+    origin: (usize, usize),
+    target: (usize, usize),
+    aim: Aim,
+) -> Vec<(usize, usize)> {
+    match aim.area {
+        Some(area) => tiles_in_area(map, query_tiles, origin, target, area),
+        None => line_of_fire(map, query_tiles, origin, target)
+            .path
+            .last()
+            .copied()
+            .into_iter()
+            .collect(),
+    }
+}
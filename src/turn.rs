@@ -0,0 +1,89 @@
+use crate::resources::*;
+use bevy::prelude::*;
+
+pub struct TurnPlugin;
+
+impl Plugin for TurnPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<GameState>()
+            .add_sub_state::<TurnState>()
+            .configure_sets(
+                Update,
+                (
+                    TurnSet::AwaitingInput.run_if(in_state(TurnState::AwaitingInput)),
+                    TurnSet::Targeting.run_if(in_state(TurnState::Targeting)),
+                    TurnSet::ItemMenu.run_if(in_state(TurnState::ItemMenu)),
+                    TurnSet::PlayerTurn.run_if(in_state(TurnState::PlayerTurn)),
+                    TurnSet::MonsterTurn.run_if(in_state(TurnState::MonsterTurn)),
+                    TurnSet::EndTurn.run_if(in_state(TurnState::EndTurn)),
+                )
+                    .chain(),
+            )
+            .init_resource::<TurnCount>()
+            .add_systems(
+                Update,
+                advance_turn
+                    .after(TurnSet::EndTurn)
+                    .run_if(in_state(GameState::Playing)),
+            );
+    }
+}
+
+/// Entering `Playing` generates a fresh dungeon. Leaving `GameOver` clears the old one away.
+#[derive(States, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum GameState {
+    #[default]
+    Playing,
+    GameOver,
+}
+
+/// Only exists while `Playing`, so every new run starts back at `EndTurn`.
+/// The game starts in `EndTurn` so the player's first view is worked out before any input.
+#[derive(SubStates, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[source(GameState = GameState::Playing)]
+pub enum TurnState {
+    AwaitingInput,
+    /// Picking where to shoot. Confirming takes the turn, cancelling goes back to input.
+    Targeting,
+    /// Picking an item from the pack.
+    ItemMenu,
+    PlayerTurn,
+    MonsterTurn,
+    #[default]
+    EndTurn,
+}
+
+/// Systems that take part in a turn go in the set of the phase they belong to.
+/// Each set only runs while its `TurnState` is active.
+#[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TurnSet {
+    AwaitingInput,
+    Targeting,
+    ItemMenu,
+    PlayerTurn,
+    MonsterTurn,
+    EndTurn,
+}
+
+fn advance_turn(
+    state: Res<State<TurnState>>,
+    turn_order: Res<TurnOrder>,
+    mut turn_count: ResMut<TurnCount>,
+    mut next_state: ResMut<NextState<TurnState>>,
+) {
+    match state.get() {
+        // Leaving AwaitingInput, Targeting or ItemMenu is up to the player's input.
+        TurnState::AwaitingInput | TurnState::Targeting | TurnState::ItemMenu => {}
+        TurnState::PlayerTurn => {
+            turn_count.0 += 1;
+            next_state.set(TurnState::MonsterTurn)
+        }
+        // Monsters keep getting rounds until nobody but the player is ready to act.
+        TurnState::MonsterTurn => {
+            if turn_order.acting.is_empty() {
+                next_state.set(TurnState::EndTurn)
+            }
+        }
+        TurnState::EndTurn => next_state.set(TurnState::AwaitingInput),
+    }
+}
@@ -1,149 +1,164 @@
-use crate::add_player;
 use crate::components::*;
-use crate::events::*;
+use crate::effects::Blinded;
+use crate::gamelog::GameLog;
 use crate::get_tile_idx;
-use crate::monsters::set_occupied_tiles;
-use crate::move_player;
+use crate::initiative::ActingSet;
+use crate::los::*;
+use crate::monsters::update_ai_states;
 use crate::resources::*;
+use crate::turn::*;
 use bevy::prelude::*;
-use bevy_rapier2d::prelude::*;
-use std::iter::zip;
+use std::collections::HashSet;
 
 pub struct VisibilityPlugin;
 
 impl Plugin for VisibilityPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(
-            PostUpdate,
-            (
-                get_viewshed,
-                apply_view.after(get_viewshed).after(add_player),
-            ),
-        )
-        .add_systems(
             Update,
             (
-                get_viewshed.run_if(on_event::<Tick>()),
-                apply_view.after(get_viewshed).run_if(on_event::<Tick>()),
-            )
-                .after(move_player)
-                .after(set_occupied_tiles),
+                (get_viewshed, (apply_view, show_items, announce_sightings))
+                    .chain()
+                    .in_set(TurnSet::EndTurn),
+                get_monster_viewsheds
+                    .in_set(TurnSet::MonsterTurn)
+                    .in_set(ActingSet)
+                    .before(update_ai_states),
+            ),
         );
     }
 }
 
 pub fn get_viewshed(
-    rapier_context: Res<RapierContext>,
-    mut set: ParamSet<(
-        Query<(&mut Tile, &Position, &Transform)>,
-        Query<(&mut Tile, &Position)>,
-    )>,
-    mut query_player: Query<(&Position, &Transform, &mut Viewshed), With<Player>>,
+    mut query_tiles: Query<&mut Tile>,
+    mut query_player: Query<(&Position, &mut Viewshed, Has<Blinded>), With<Player>>,
     map: Res<Map>,
 ) {
-    let (player_pos, player_transform, mut player_viewshed) =
-        query_player.get_single_mut().unwrap();
-    player_viewshed.visible_tiles.clear();
-    let player_transform_x = player_transform.translation.x;
-    let player_transform_y = player_transform.translation.y;
-
-    let player_tile_ent = map.tiles[get_tile_idx(player_pos.x, player_pos.y)];
-
-    for (mut tile, _pos, _trans) in set.p0().iter_mut() {
-        match tile.visibletype {
-            VisibleType::Visible => {
-                tile.visibletype = VisibleType::Memoried;
-            }
-            VisibleType::Memoried => {}
-            _ => {}
+    let (player_pos, mut player_viewshed, blinded) = query_player.get_single_mut().unwrap();
+    let visible_tiles: Vec<Entity> = field_of_view(
+        &map,
+        &query_tiles.to_readonly(),
+        (player_pos.x, player_pos.y),
+        if blinded { 0 } else { player_viewshed.range },
+    )
+    .into_iter()
+    .map(|(x, y)| map.tiles[get_tile_idx(x, y)])
+    .collect();
+
+    let in_view: HashSet<Entity> = visible_tiles.iter().copied().collect();
+    for &tile_ent in map.tiles.iter() {
+        let mut tile = query_tiles.get_mut(tile_ent).unwrap();
+        let visibletype = if in_view.contains(&tile_ent) {
+            VisibleType::Visible
+        } else if tile.visibletype == VisibleType::Visible {
+            VisibleType::Memoried
+        } else {
+            tile.visibletype
+        };
+        if tile.visibletype != visibletype {
+            tile.visibletype = visibletype;
         }
     }
+    player_viewshed.visible_tiles = visible_tiles;
+}
 
-    for deg in (1..=360).step_by(1) {
-        let radian = (deg as f32).to_radians();
-        let radius = 60.0;
-        let x = radius * radian.cos();
-        let y = radius * radian.sin();
-
-        let visible_tiles = cast_ray(
-            &rapier_context,
-            player_transform_x,
-            player_transform_y,
-            x,
-            y,
-            player_tile_ent,
-            set.p1(),
-        );
-
-        for tile in visible_tiles {
-            set.p1().get_mut(tile).unwrap().0.visibletype = VisibleType::Visible;
-            player_viewshed.visible_tiles.push(tile);
+/// Mentions monsters as they come into view.
+fn announce_sightings(
+    query_monsters: Query<(Entity, &Monster, &Name)>,
+    query_player: Query<&Viewshed, With<Player>>,
+    turn_count: Res<TurnCount>,
+    mut log: ResMut<GameLog>,
+    mut in_view: Local<Vec<Entity>>,
+) {
+    let player_viewshed = query_player.single();
+    let mut now_in_view = Vec::new();
+    for (entity, monster, name) in query_monsters.iter() {
+        if !player_viewshed
+            .visible_tiles
+            .contains(&monster.occupied_tile)
+        {
+            continue;
+        }
+        if !in_view.contains(&entity) {
+            let article = if name.starts_with(['A', 'E', 'I', 'O', 'U']) {
+                "an"
+            } else {
+                "a"
+            };
+            log.add(
+                turn_count.0,
+                format!("You see {article} {name}"),
+                Color::srgb(1.0, 1.0, 0.0),
+            );
         }
+        now_in_view.push(entity);
     }
+    *in_view = now_in_view;
 }
 
-fn cast_ray(
-    rapier_context: &Res<RapierContext>,
-    player_x: f32,
-    player_y: f32,
-    tile_x: f32,
-    tile_y: f32,
-    player_tile_ent: Entity,
-    custom_query: Query<(&mut Tile, &Position)>,
-) -> Vec<Entity> {
-    let ray_origin = Vec2::new(player_x, player_y);
-    let ray_dir = Vec2::new(tile_x, tile_y);
-    let max_toi = 1.0;
-    let solid = false;
-    let n = QueryFilter::new();
-    let predicate = |handle| {
-        custom_query
-            .get(handle)
-            .is_ok_and(|tile| tile.0.tiletype == TileType::Wall)
-    };
-    let filter = QueryFilter::exclude_rigid_body(n, player_tile_ent).predicate(&predicate);
-    let filter2 = QueryFilter::exclude_rigid_body(n, player_tile_ent);
-
-    let mut intersected_tiles: Vec<Entity> = Vec::new();
-    let mut tile_intersections: Vec<RayIntersection> = Vec::new();
-
-    rapier_context.intersections_with_ray(
-        ray_origin,
-        ray_dir,
-        max_toi,
-        solid,
-        filter2,
-        |entity, intersection| {
-            intersected_tiles.push(entity);
-            tile_intersections.push(intersection);
-            true
-        },
-    );
-
-    let mut visible_tiles: Vec<Entity> = Vec::new();
-    let nearest_wall: Option<(Entity, f32)>;
-    // cast for nearest wall
-    if let Some((entity, toi)) =
-        rapier_context.cast_ray(ray_origin, ray_dir, max_toi, solid, filter)
-    {
-        nearest_wall = Some((entity, toi));
-        // add nearest wall to visible tiles
-        if let Some(wall) = nearest_wall {
-            visible_tiles.push(wall.0);
+/// Items show while in view, and stay drawn in grey once the player has seen them. Anyone
+/// standing on an item in view hides it.
+#[allow(clippy::type_complexity)]
+fn show_items(
+    mut commands: Commands,
+    mut query_items: Query<
+        (
+            Entity,
+            &Position,
+            &Renderable,
+            &mut Visibility,
+            Option<&mut Text>,
+            Has<Remembered>,
+        ),
+        With<Item>,
+    >,
+    query_occupied: Query<(), With<Occupied>>,
+    query_player: Query<(&Position, &Viewshed), With<Player>>,
+    map: Res<Map>,
+) {
+    let (player_pos, viewshed) = query_player.single();
+    for (item, position, renderable, mut visibility, text, remembered) in query_items.iter_mut() {
+        let tile = map.tiles[get_tile_idx(position.x, position.y)];
+        let in_view = viewshed.visible_tiles.contains(&tile);
+        let covered = in_view
+            && (query_occupied.contains(tile)
+                || (position.x, position.y) == (player_pos.x, player_pos.y));
+        if in_view && !remembered {
+            commands.entity(item).insert(Remembered);
         }
-
-        for (ent, intersect) in zip(intersected_tiles.clone(), tile_intersections) {
-            if custom_query.get(ent).unwrap().0.tiletype != TileType::Wall
-                && intersect.time_of_impact < nearest_wall.unwrap().1
-            {
-                visible_tiles.push(ent);
-            }
+        *visibility = if (in_view || remembered) && !covered {
+            Visibility::Visible
+        } else {
+            Visibility::Hidden
+        };
+        if let Some(mut text) = text {
+            text.sections[0].style.color = if in_view {
+                renderable.color
+            } else {
+                Color::srgba(1.0, 1.0, 1.0, 0.5)
+            };
         }
-    } else {
-        visible_tiles.append(&mut intersected_tiles);
     }
+}
 
-    visible_tiles
+/// Works out what each monster about to act can see.
+pub fn get_monster_viewsheds(
+    mut query_monsters: Query<(&Position, &mut Viewshed, Has<Blinded>), With<Monster>>,
+    query_tiles: Query<&Tile>,
+    turn_order: Res<TurnOrder>,
+    map: Res<Map>,
+) {
+    for &monster in turn_order.acting.iter() {
+        let Ok((position, mut viewshed, blinded)) = query_monsters.get_mut(monster) else {
+            continue;
+        };
+        let range = if blinded { 0 } else { viewshed.range };
+        let visible_tiles = field_of_view(&map, &query_tiles, (position.x, position.y), range);
+        viewshed.visible_tiles = visible_tiles
+            .into_iter()
+            .map(|(x, y)| map.tiles[get_tile_idx(x, y)])
+            .collect();
+    }
 }
 
 pub fn apply_view(
@@ -154,15 +169,11 @@ pub fn apply_view(
 ) {
     for (tile, mut text) in query_occupied.iter_mut() {
         match tile.visibletype {
-            VisibleType::Visible => {
-                if tile.tiletype == TileType::Floor {
-                    text.sections[0].style.color = Color::srgba(0.0, 1.0, 0.0, 0.0);
-                }
+            VisibleType::Visible if tile.tiletype == TileType::Floor => {
+                text.sections[0].style.color = Color::srgba(0.0, 1.0, 0.0, 0.0);
             }
-            VisibleType::Memoried => {
-                if tile.tiletype == TileType::Floor {
-                    text.sections[0].style.color = Color::srgba(1.0, 1.0, 1.0, 0.5);
-                }
+            VisibleType::Memoried if tile.tiletype == TileType::Floor => {
+                text.sections[0].style.color = Color::srgba(1.0, 1.0, 1.0, 0.5);
             }
             _ => {}
         }
@@ -171,18 +182,10 @@ pub fn apply_view(
     for (tile, mut text) in query_unoccupied.iter_mut() {
         match tile.visibletype {
             VisibleType::Visible => {
-                if tile.tiletype == TileType::Floor {
-                    text.sections[0].style.color = Color::srgba(0.0, 1.0, 0.0, 1.0);
-                } else if tile.tiletype == TileType::Wall {
-                    text.sections[0].style.color = Color::srgba(0.0, 1.0, 0.0, 1.0);
-                }
+                text.sections[0].style.color = Color::srgba(0.0, 1.0, 0.0, 1.0);
             }
             VisibleType::Memoried => {
-                if tile.tiletype == TileType::Floor {
-                    text.sections[0].style.color = Color::srgba(1.0, 1.0, 1.0, 0.5);
-                } else if tile.tiletype == TileType::Wall {
-                    text.sections[0].style.color = Color::srgba(1.0, 1.0, 1.0, 0.5);
-                }
+                text.sections[0].style.color = Color::srgba(1.0, 1.0, 1.0, 0.5);
             }
             _ => {}
         }